@@ -42,6 +42,10 @@ fn main() {
     println!("cargo:rerun-if-env-changed=LIBCLANG_PATH");
 
     println!("cargo:rustc-link-lib=dylib=tux");
+    // _tmstartserver() for servers written in Rust, only with the server API
+    if env::var_os("CARGO_FEATURE_SERVER").is_some() {
+        println!("cargo:rustc-link-lib=dylib=atmisrvinteg");
+    }
 
     // --- 3) (Optional) compile any bundled C sources -------------------------
     // If you have .c files, add them here; otherwise you can remove this block.
@@ -0,0 +1,202 @@
+// examples/test_server.rs
+//
+// XATMI server used by the server tests (`cargo test --features server -- --ignored`).
+// Build it with `cargo build --features server --example test_server` and boot it in the test
+// application, e.g. in ndrxconfig.xml:
+//
+//   <server name="test_server">
+//       <srvid>100</srvid>
+//       <min>1</min>
+//       <max>1</max>
+//       <mindispatchthreads>2</mindispatchthreads>
+//       <maxdispatchthreads>2</maxdispatchthreads>
+//       <sysopt>-e ${NDRX_ULOG}/test_server.log -r</sysopt>
+//   </server>
+use endurox_rs::{run_server, set_global_deadline_field, AtmiCtx, AtmiError, AtmiResult, CallFlags,
+    DeferredRequest, PollerFd, Server, ServiceReply, TypedBuffer};
+
+use std::{
+    ffi::CStr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// BFLD_LONG field 1001 carrying the deadline, same as in the tests
+const DEADLINE_FLD: i32 = (1 << 25) | 1001;
+
+/// Dispatch threads started by `thread_init`
+static THREADS: AtomicU32 = AtomicU32::new(0);
+
+/// Poller callback of the pipe was invoked
+static POLLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct TestServer {
+    /// Registration of the pipe read end, dropped before the pipe
+    poller: Option<PollerFd>,
+    pipe: Option<(OwnedFd, OwnedFd)>,
+}
+
+/// STRING buffer with `text`.
+fn string_buf<'a>(ctx: &'a AtmiCtx, text: &str) -> AtmiResult<TypedBuffer<'a>> {
+    let buf = ctx.tpalloc("STRING", "", text.len() + 1)?;
+    unsafe {
+        std::ptr::copy_nonoverlapping(text.as_ptr(), buf.as_ptr() as *mut u8, text.len());
+        *buf.as_ptr().add(text.len()) = 0;
+    }
+    Ok(buf)
+}
+
+/// Pipe as (read end, write end).
+fn pipe() -> AtmiResult<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(AtmiError::new(AtmiError::TPEOS, "pipe() failed"));
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+impl Server for TestServer {
+    fn init(&mut self, ctx: &AtmiCtx, _args: &[String]) -> AtmiResult<()> {
+        // Reply with the request buffer itself
+        ctx.advertise("RSECHO", |_ctx, mut info| {
+            Ok(ServiceReply::success(info.take_data()))
+        })?;
+
+        // Reply with the request buffer after 2 seconds
+        ctx.advertise_sync("RSSLOW", |_ctx, mut info| {
+            thread::sleep(Duration::from_secs(2));
+            Ok(ServiceReply::success(info.take_data()))
+        })?;
+
+        // Reply with rcode 1 if the request buffer is given only once and
+        // the view is null after it
+        ctx.advertise("RSTAKE", |_ctx, mut info| {
+            let once = info.take_data().is_some() && info.take_data().is_none();
+            let once = once && info.data().as_ptr().is_null();
+            Ok(ServiceReply::Success { rcode: once as i64, data: None })
+        })?;
+
+        // Reply with the length of the STRING request as rcode, read
+        // through the borrowed view
+        ctx.advertise("RSLEN", |_ctx, info| {
+            let data = info.data();
+            let len = unsafe { CStr::from_ptr(data.as_ptr()) }.to_bytes().len();
+            Ok(ServiceReply::Success { rcode: len as i64, data: None })
+        })?;
+
+        // Reply with the number of started dispatch threads as rcode
+        ctx.advertise_sync("RSTHREADS", |_ctx, _info| {
+            let threads = THREADS.load(Ordering::Relaxed);
+            Ok(ServiceReply::Success { rcode: threads as i64, data: None })
+        })?;
+
+        // Reply with rcode 1 if the request came with a deadline
+        set_global_deadline_field(Some(DEADLINE_FLD));
+        ctx.advertise_sync("RSDEADLINE", |ctx, _info| {
+            Ok(ServiceReply::Success { rcode: ctx.deadline().is_some() as i64, data: None })
+        })?;
+
+        // Conversation: receives control with the request, sends "pong" and
+        // ends with TPSUCCESS rcode 3, or TPFAIL rcode 4 for request "fail"
+        ctx.advertise("RSCONV", |ctx, info| {
+            let Some(mut conv) = info.conversation() else {
+                return Err(AtmiError::new(AtmiError::TPEPROTO, "RSCONV needs tpconnect"));
+            };
+            let fail = unsafe { CStr::from_ptr(info.data().as_ptr()) }.to_bytes() == b"fail";
+
+            conv.send(&mut string_buf(ctx, "pong")?, CallFlags::empty())?;
+
+            Ok(if fail {
+                ServiceReply::Fail { rcode: 4, data: None }
+            } else {
+                ServiceReply::Success { rcode: 3, data: None }
+            })
+        })?;
+
+        // Reply with TPFAIL and rcode 5
+        ctx.advertise("RSFAIL", |_ctx, _info| {
+            Ok(ServiceReply::Fail { rcode: 5, data: None })
+        })?;
+
+        // Reply with TPFAIL and the request buffer
+        ctx.advertise("RSFAILECHO", |_ctx, mut info| {
+            Ok(ServiceReply::Fail { rcode: 5, data: info.take_data() })
+        })?;
+
+        // Deferred request completed by a worker thread with rcode 7
+        ctx.advertise("RSDEFER", |_ctx, info| {
+            let req = info.defer()?;
+            on_worker(req, |ctx, req| {
+                let _ = req.complete(ctx, ServiceReply::Success { rcode: 7, data: None });
+            });
+            Ok(ServiceReply::Deferred)
+        })?;
+
+        // Deferred request dropped by a worker thread, answered with TPFAIL
+        ctx.advertise("RSDEFDROP", |_ctx, info| {
+            let req = info.defer()?;
+            on_worker(req, |_ctx, req| drop(req));
+            Ok(ServiceReply::Deferred)
+        })?;
+
+        // Handler fails after defer(), the request is dropped within the
+        // handler and answered by the dispatcher with TPFAIL
+        ctx.advertise("RSDEFERR", |ctx, info| {
+            let _req = info.defer()?;
+            ctx.tpalloc("NO_SUCH_TYPE", "", 0)?;
+            Ok(ServiceReply::Deferred)
+        })?;
+
+        // Pipe registered in the poller; the byte written here is read by the
+        // callback after init returns
+        let (rd, wr) = pipe()?;
+        let rd_fd = rd.as_raw_fd();
+        self.poller = Some(ctx.add_poller_fd(&rd, libc::POLLIN as u32, move |_events, _ctx| {
+            let mut byte = 0u8;
+            unsafe { libc::read(rd_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            POLLED.store(true, Ordering::Relaxed);
+            Ok(())
+        })?);
+        unsafe { libc::write(wr.as_raw_fd(), b"x".as_ptr() as *const libc::c_void, 1) };
+        self.pipe = Some((rd, wr));
+
+        // Reply with rcode 1 once the poller callback was invoked
+        ctx.advertise_sync("RSPOLLED", |_ctx, _info| {
+            let polled = POLLED.load(Ordering::Relaxed);
+            Ok(ServiceReply::Success { rcode: polled as i64, data: None })
+        })?;
+
+        Ok(())
+    }
+
+    fn done(&mut self, _ctx: &AtmiCtx) {
+        self.poller = None;
+        self.pipe = None;
+    }
+
+    fn thread_init(&self, _ctx: &AtmiCtx, _args: &[String]) -> AtmiResult<()> {
+        THREADS.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_done(&self, _ctx: &AtmiCtx) {
+        THREADS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Hand the deferred request over to a new thread with its own context.
+fn on_worker(req: DeferredRequest, f: impl FnOnce(&AtmiCtx, DeferredRequest) + Send + 'static) {
+    thread::spawn(move || {
+        let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+        f(&ctx, req);
+    });
+}
+
+fn main() {
+    std::process::exit(run_server(TestServer::default()));
+}
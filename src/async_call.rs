@@ -0,0 +1,306 @@
+// src/async_call.rs
+use core::ffi::c_int;
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, BlkTimeScope, CallDescriptor, CallError, CallFlags,
+    CallResult, TypedBuffer};
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// Poll interval for the replies while calls are outstanding. The reply
+/// thread waits for new commands meanwhile, so they are sent at once.
+const REPLY_POLL: Duration = Duration::from_millis(10);
+
+type BuildFn = Box<dyn for<'a> FnOnce(&'a AtmiCtx) -> AtmiResult<TypedBuffer<'a>> + Send>;
+type ReplyFn = Box<dyn for<'a> FnOnce(CallResult<'a, TypedBuffer<'a>>) + Send>;
+
+enum Command {
+    Call {
+        id: u64,
+        service: String,
+        flags: CallFlags,
+        build: BuildFn,
+        reply: ReplyFn,
+    },
+    Cancel {
+        id: u64,
+    },
+}
+
+struct Pending<'ctx> {
+    id: u64,
+    service: String,
+    sent: Instant,
+    call: CallDescriptor<'ctx>,
+    reply: ReplyFn,
+}
+
+/// Async XATMI calls for tokio (feature "tokio").
+///
+/// Owns a reply thread with its own joined context, which issues *tpacall(3)*
+/// and collects the replies with *tpgetrply(3)* (TPGETANY), so no runtime
+/// thread is blocked. As buffers are bound to the context, the request is
+/// built and the reply parsed by closures run on the reply thread.
+///
+/// The reply thread waits for new calls on its channel and, while calls are
+/// outstanding, takes the arrived replies every 10 ms with *tpgetrply(3)*
+/// (TPNOBLOCK), so new calls are not delayed by the outstanding ones. Calls without reply fail with `TPETIME` after the blocking time of the
+/// context (*tpgblktime(3)*), or `NDRX_TOUT` if not set.
+#[derive(Debug)]
+pub struct AsyncCaller {
+    tx: Option<mpsc::Sender<Command>>,
+    next_id: AtomicU64,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AsyncCaller {
+    /// Start the reply thread, context joined with plain `tpinit`.
+    pub fn new() -> AtmiResult<Self> {
+        Self::with_init(|ctx: &AtmiCtx| ctx.tpinit())
+    }
+
+    /// Start the reply thread, context joined with `init`.
+    pub fn with_init<F>(init: F) -> AtmiResult<Self>
+    where
+        F: FnOnce(&AtmiCtx) -> AtmiResult<()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+
+        let thread = thread::Builder::new()
+            .name("atmi-async-reply".into())
+            .spawn(move || {
+                let ctx = match AtmiCtx::new().and_then(|ctx| init(&ctx).map(|()| ctx)) {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                let _ = ready_tx.send(Ok(()));
+                reply_thread(&ctx, &rx);
+            })
+            .map_err(|e| AtmiError::new(raw::TPEOS, format!("failed to spawn reply thread: {e}")))?;
+
+        let caller = AsyncCaller { tx: Some(tx), next_id: AtomicU64::new(0), thread: Some(thread) };
+
+        ready_rx.recv().unwrap_or_else(|_| {
+            Err(AtmiError::new(raw::TPESYSTEM, "reply thread exited during init"))
+        })?;
+
+        Ok(caller)
+    }
+
+    /// Call `service` asynchronously (tpacall) and await the reply.
+    ///
+    /// Dropping the future before the reply arrives cancels the call (tpcancel).
+    /// See *tpacall(3)* for more details.
+    ///
+    /// # Parameters
+    ///
+    /// * `service` – name of the service to call.
+    /// * `flags` – call flags, `TPNOREPLY` is not allowed.
+    /// * `build` – makes the request buffer on the reply thread context.
+    /// * `parse` – converts the outcome of the call to the result; a failed
+    ///   call gives `CallError` with the user return code and the reply
+    ///   buffer sent with TPFAIL, `?` turns it into `AtmiError`.
+    pub async fn call<B, P, R>(
+        &self,
+        service: &str,
+        flags: CallFlags,
+        build: B,
+        parse: P,
+    ) -> AtmiResult<R>
+    where
+        B: for<'a> FnOnce(&'a AtmiCtx) -> AtmiResult<TypedBuffer<'a>> + Send + 'static,
+        P: for<'a> FnOnce(CallResult<'a, TypedBuffer<'a>>) -> AtmiResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        if flags.contains(CallFlags::TPNOREPLY) {
+            return Err(AtmiError::new(raw::TPEINVAL, "TPNOREPLY is not supported by async call"));
+        }
+
+        let Some(tx) = &self.tx else {
+            return Err(stopped());
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        let cmd = Command::Call {
+            id,
+            service: service.to_owned(),
+            flags,
+            build: Box::new(build),
+            reply: Box::new(move |rc| {
+                let _ = reply_tx.send(parse(rc));
+            }),
+        };
+
+        tx.send(cmd).map_err(|_| stopped())?;
+
+        let mut cancel = CancelOnDrop { tx, id, armed: true };
+        let rc = reply_rx.await.unwrap_or_else(|_| Err(stopped()));
+        cancel.armed = false;
+
+        rc
+    }
+}
+
+impl Drop for AsyncCaller {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn stopped() -> AtmiError {
+    AtmiError::new(raw::TPESYSTEM, "async reply thread stopped")
+}
+
+/// Cancels the call if the future is dropped before the reply.
+struct CancelOnDrop<'a> {
+    tx: &'a mpsc::Sender<Command>,
+    id: u64,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = self.tx.send(Command::Cancel { id: self.id });
+        }
+    }
+}
+
+fn reply_thread(ctx: &AtmiCtx, rx: &mpsc::Receiver<Command>) {
+    let mut pending: HashMap<c_int, Pending<'_>> = HashMap::new();
+    let timeout = call_timeout(ctx);
+
+    loop {
+        // Block while idle, otherwise wait for commands until the next poll
+        if pending.is_empty() {
+            match rx.recv() {
+                Ok(cmd) => run_command(ctx, &mut pending, cmd),
+                Err(_) => break,
+            }
+        } else {
+            match rx.recv_timeout(REPLY_POLL) {
+                Ok(cmd) => run_command(ctx, &mut pending, cmd),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        loop {
+            match rx.try_recv() {
+                Ok(cmd) => run_command(ctx, &mut pending, cmd),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        collect_replies(ctx, &mut pending);
+        expire(&mut pending, timeout);
+    }
+
+    // Outstanding calls are cancelled by the descriptors' drop
+}
+
+/// Blocking time of the context (tpgblktime), or `NDRX_TOUT` if not set.
+fn call_timeout(ctx: &AtmiCtx) -> Option<Duration> {
+    match ctx.tpgblktime(BlkTimeScope::All) {
+        Ok(timeout) if !timeout.is_zero() => Some(timeout),
+        _ => env::var("NDRX_TOUT")
+            .ok()
+            .and_then(|secs| secs.trim().parse().ok())
+            .map(Duration::from_secs),
+    }
+}
+
+fn run_command<'ctx>(ctx: &'ctx AtmiCtx, pending: &mut HashMap<c_int, Pending<'ctx>>, cmd: Command) {
+    match cmd {
+        Command::Call { id, service, flags, build, reply } => {
+            let rc = build(ctx).and_then(|mut buf| ctx.tpacall(&service, &mut buf, flags));
+
+            match rc {
+                Ok(Some(call)) => {
+                    let sent = Instant::now();
+                    pending.insert(call.cd(), Pending { id, service, sent, call, reply });
+                }
+                Ok(None) => reply(Err(AtmiError::new(raw::TPEINVAL, "no reply expected").into())),
+                Err(e) => reply(Err(CallError::from(e).with_service(service))),
+            }
+        }
+        Command::Cancel { id } => {
+            let cd = pending.iter().find(|(_, p)| p.id == id).map(|(cd, _)| *cd);
+
+            if let Some(p) = cd.and_then(|cd| pending.remove(&cd)) {
+                let _ = p.call.cancel();
+            }
+        }
+    }
+}
+
+/// Take the replies already arrived, without blocking.
+fn collect_replies<'ctx>(ctx: &'ctx AtmiCtx, pending: &mut HashMap<c_int, Pending<'ctx>>) {
+    while !pending.is_empty() {
+        let (cd, rc) = match ctx.getrply_any(CallFlags::TPNOBLOCK) {
+            Ok((cd, buf)) => (Some(cd), Ok(buf)),
+            Err(e) => (e.cd, Err(e)),
+        };
+
+        match (cd.and_then(|cd| pending.remove(&cd)), rc) {
+            (Some(p), rc) => (p.reply)(rc),
+            // Reply of a call cancelled meanwhile
+            (None, Ok(_)) => {}
+            // Nothing (more) arrived
+            (None, Err(e)) if e.code == AtmiError::TPEBLOCK || e.code == AtmiError::TPETIME => break,
+            // Failure not bound to a call, the replies can't be told apart
+            (None, Err(e)) => {
+                crate::tp_error!(ctx, "Async reply collection failed: {}", e);
+                fail_all(pending, e.error);
+            }
+        }
+    }
+}
+
+/// Fail all outstanding calls with `error`, the descriptors are cancelled.
+fn fail_all(pending: &mut HashMap<c_int, Pending<'_>>, error: AtmiError) {
+    for (cd, p) in pending.drain() {
+        let err = CallError::from(error.clone()).with_service(p.service).with_cd(cd);
+        (p.reply)(Err(err));
+    }
+}
+
+/// Fail the calls without reply within `timeout` with `TPETIME`.
+fn expire(pending: &mut HashMap<c_int, Pending<'_>>, timeout: Option<Duration>) {
+    let Some(timeout) = timeout else {
+        return;
+    };
+
+    let expired: Vec<c_int> = pending
+        .iter()
+        .filter(|(_, p)| p.sent.elapsed() >= timeout)
+        .map(|(cd, _)| *cd)
+        .collect();
+
+    for cd in expired {
+        if let Some(p) = pending.remove(&cd) {
+            let err = AtmiError::new(raw::TPETIME, format!("no reply within {}s", timeout.as_secs()));
+            (p.reply)(Err(CallError::from(err).with_service(p.service).with_cd(cd)));
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use core::ffi::{c_int, c_long, c_char};
 use crate::{raw, AtmiError, AtmiResult, TypedBuffer, TypedUbf, UbfError, NstdError};
+use crate::call_descriptor::CallRegistry;
+use crate::retry::CircuitBreaker;
 
 use std::{
+    cell::Cell,
     ffi::{CStr, CString},
     marker::PhantomData,
     ptr,
+    sync::Arc,
+    time::Instant,
 };
 
 
@@ -16,18 +21,67 @@ type CtxMarker = std::rc::Rc<()>; // -> !Send & !Sync
 type CtxMarker = std::cell::Cell<()>; // -> Send & !Sync
 
 #[cfg(feature = "ctx-send")]
-type CtxHandle = raw::TPCONTEXT_T;
+pub(crate) type CtxHandle = raw::TPCONTEXT_T;
+
 
 /// Per-thread XATMI context.
 /// - Default (no "ctx-send"): !Send & !Sync
-/// - With "ctx-send": Send & !Sync
+/// - With "ctx-send": Send & !Sync. Own C context (tpnewctxt), which is
+///   switched to the calling thread for the duration of each call.
 #[derive(Debug)]
 pub struct AtmiCtx {
 
     _marker: PhantomData<CtxMarker>,
 
+    /// Outstanding tpacall descriptors
+    pub(crate) calls: CallRegistry,
+
+    /// Circuit breaker of `tpcall_opts`
+    pub(crate) breaker: Option<Arc<CircuitBreaker>>,
+
+    /// Deadline of the calls, see `set_deadline`
+    pub(crate) deadline: Cell<Option<Instant>>,
+
+    /// Terminate the session (tpterm) when context is dropped
+    term_on_drop: bool,
+
+    /// tpinit performed by this context (and not yet tpterm), only such
+    /// context terminates the session on drop
+    inited: Cell<bool>,
+
+    /// Session joined with tpinit (and not yet terminated). Without
+    /// "ctx-send" the session belongs to the thread, see `THREAD_JOINED`.
+    #[cfg(feature = "ctx-send")]
+    joined: Cell<bool>,
+
     #[cfg(feature = "ctx-send")]
     handle: CtxHandle,
+
+    /// Nesting of `with_ctx` calls, the handle is switched by the outermost
+    #[cfg(feature = "ctx-send")]
+    switch_depth: Cell<u32>,
+
+    /// TPESYSTEM/TPEPROTO seen since the last `take_fault`, see `AtmiCtxPool`
+    #[cfg(feature = "ctx-send")]
+    fault: Cell<bool>,
+
+    /// Handle allocated by us (tpnewctxt), freed on drop
+    #[cfg(feature = "ctx-send")]
+    owns_handle: bool,
+}
+
+// SAFETY: the C context handle is owned by this value and not tied to the
+// thread which made it: `with_ctx` switches it in (tpsetctxt) on the calling
+// thread for each call and out again. The value stays !Sync, so the handle
+// is never used by two threads at once.
+#[cfg(feature = "ctx-send")]
+unsafe impl Send for AtmiCtx {}
+
+// Without "ctx-send" all contexts of the thread share the C context of the
+// thread, so the session state is kept per thread, not per `AtmiCtx`.
+#[cfg(not(feature = "ctx-send"))]
+thread_local! {
+    static THREAD_JOINED: Cell<bool> = const { Cell::new(false) };
 }
 
 impl AtmiCtx {
@@ -38,32 +92,152 @@ impl AtmiCtx {
         // No ctx-send: just a thread-local marker, nothing to allocate.
         #[cfg(not(feature = "ctx-send"))]
         {
-            Ok(AtmiCtx { _marker: PhantomData })
+            Ok(AtmiCtx {
+                _marker: PhantomData,
+                calls: CallRegistry::default(),
+                breaker: None,
+                deadline: Cell::new(None),
+                term_on_drop: true,
+                inited: Cell::new(false),
+            })
+        }
+
+        // With ctx-send: allocate context on C side via tpnewctxt(0, 0),
+        // i.e. not auto-destroyed with the thread and not set to the thread.
+        #[cfg(feature = "ctx-send")]
+        {
+            let handle = unsafe { raw::tpnewctxt(0, 0) };
+
+            if handle.is_null() {
+                return Err(AtmiError::new(
+                    raw::TPESYSTEM,
+                    "Failed to allocate new context - see ULOG for details",
+                ));
+            }
+
+            Ok(AtmiCtx {
+                _marker: PhantomData,
+                calls: CallRegistry::default(),
+                breaker: None,
+                deadline: Cell::new(None),
+                term_on_drop: true,
+                inited: Cell::new(false),
+                joined: Cell::new(false),
+                handle,
+                switch_depth: Cell::new(0),
+                fault: Cell::new(false),
+                owns_handle: true,
+            })
+        }
+    }
+
+    /// Wrap the context XATMI already runs on this thread, e.g. the server
+    /// dispatcher. The session is owned by XATMI, thus tpterm is not called on drop.
+    pub(crate) fn attach_current() -> AtmiResult<Self> {
+
+        #[cfg(not(feature = "ctx-send"))]
+        {
+            THREAD_JOINED.with(|j| j.set(true));
+            Ok(AtmiCtx {
+                _marker: PhantomData,
+                calls: CallRegistry::default(),
+                breaker: None,
+                deadline: Cell::new(None),
+                term_on_drop: false,
+                inited: Cell::new(false),
+            })
         }
 
-        // With ctx-send: allocate context on C side via tpnewctxt(0, 0).
         #[cfg(feature = "ctx-send")]
         {
-            unsafe {
-                // Adjust signature to your actual raw binding:
-                // e.g. fn tpnewctxt(flags: i64, rsvd: i64) -> raw::TPCONTEXT_T;
-                let handle = raw::tpnewctxt(0, 0);
-
-                // If TPCONTEXT_T is a pointer type, check for null:
-                // if handle.is_null() { ... }
-
-                if handle_invalid(handle) {
-                    // replace `handle_invalid` with your real check if needed
-                    return Err(AtmiError::new(
-                        raw::TPESYSTEM,
-                        "Failed to allocate new context - see ULOG for details",
-                    ));
-                }
-
-                Ok(AtmiCtx {
-                    _marker: PhantomData,
-                    handle,
-                })
+            // tpgetctxt() detaches the context from the thread, put it back.
+            let mut handle: CtxHandle = ptr::null_mut();
+            let rc = unsafe { raw::tpgetctxt(&mut handle, 0) };
+
+            if rc == raw::EXFAIL as c_int || handle.is_null() {
+                return Err(AtmiError::new(
+                    raw::TPESYSTEM,
+                    "Failed to get current context - see ULOG for details",
+                ));
+            }
+
+            unsafe { raw::tpsetctxt(handle, 0) };
+
+            Ok(AtmiCtx {
+                _marker: PhantomData,
+                calls: CallRegistry::default(),
+                breaker: None,
+                deadline: Cell::new(None),
+                term_on_drop: false,
+                inited: Cell::new(false),
+                joined: Cell::new(true),
+                handle,
+                switch_depth: Cell::new(0),
+                fault: Cell::new(false),
+                owns_handle: false,
+            })
+        }
+    }
+
+    /// Run `f` with this context set for the current thread.
+    ///
+    /// With "ctx-send" the context handle is switched in (tpsetctxt) and the
+    /// previous context of the thread restored afterwards, so that a context
+    /// moved to another thread still sees its own session, tperrno and Ferror.
+    /// Nested calls run directly. Without "ctx-send" `f` is just called.
+    /// The previous context is restored also if `f` panics.
+    #[inline]
+    pub(crate) fn with_ctx<R>(&self, f: impl FnOnce() -> R) -> R {
+
+        #[cfg(not(feature = "ctx-send"))]
+        {
+            f()
+        }
+
+        #[cfg(feature = "ctx-send")]
+        {
+            let _switched = SwitchGuard { ctx: self, prev: self.switch_in() };
+            f()
+        }
+    }
+
+    /// Make this context current for the thread (tpsetctxt).
+    ///
+    /// Returns the previous context of the thread, or `None` if this context
+    /// was already switched in by the outer call. Pair with `switch_out`.
+    #[cfg(feature = "ctx-send")]
+    pub(crate) fn switch_in(&self) -> Option<CtxHandle> {
+        let depth = self.switch_depth.get();
+        self.switch_depth.set(depth + 1);
+
+        if depth > 0 {
+            return None;
+        }
+
+        // tpgetctxt() also detaches the context from the thread
+        let mut prev: CtxHandle = ptr::null_mut();
+        unsafe {
+            raw::tpgetctxt(&mut prev, 0);
+            raw::tpsetctxt(self.handle, 0);
+        }
+
+        Some(prev)
+    }
+
+    /// Undo `switch_in`, restoring the previous context of the thread.
+    #[cfg(feature = "ctx-send")]
+    pub(crate) fn switch_out(&self, prev: Option<CtxHandle>) {
+        self.switch_depth.set(self.switch_depth.get() - 1);
+
+        let Some(prev) = prev else {
+            return;
+        };
+
+        // tpgetctxt() detaches this context, its handle is known already
+        unsafe {
+            raw::tpgetctxt(&mut ptr::null_mut(), 0);
+            if !prev.is_null() {
+                raw::tpsetctxt(prev, 0);
             }
         }
     }
@@ -71,59 +245,136 @@ impl AtmiCtx {
     /// Perform init (tpinit). On success current context becomes assocated with ATMI session.
     /// See *tpinit(3)* for more details.
     pub fn tpinit(&self) -> AtmiResult<()> {
-        let rc = unsafe { raw::tpinit(ptr::null_mut()) };
-        if rc == raw::EXSUCCEED as c_int {
-            Ok(())
-        } else {
-            Err(self.atmi_last_error())
-        }
+        self.tpinit_with(ptr::null_mut())
+    }
+
+    /// tpinit with the given `TPINIT` buffer (may be null), see `TpInitBuilder`.
+    pub(crate) fn tpinit_with(&self, tpinfo: *mut raw::TPINIT) -> AtmiResult<()> {
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpinit(tpinfo) };
+            if rc == raw::EXSUCCEED as c_int {
+                self.set_joined(true);
+                self.inited.set(true);
+                Ok(())
+            } else {
+                Err(self.atmi_last_error())
+            }
+        })
     }
 
     /// Perform un-init (tpterm). On success ATMI session is terminated
     /// See *tpterm(3)* for more details.
     pub fn tpterm(&self) -> AtmiResult<()> {
-        let rc = unsafe { raw::tpterm() };
-        if rc == raw::EXSUCCEED as c_int {
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpterm() };
+            if rc == raw::EXSUCCEED as c_int {
+                self.set_joined(false);
+                self.inited.set(false);
+                Ok(())
+            } else {
+                Err(self.atmi_last_error())
+            }
+        })
+    }
+
+    /// Has the context joined the application (tpinit) and not yet left (tpterm)?
+    /// Contexts of the server dispatcher are always joined.
+    /// Without "ctx-send" this is the state of the thread, shared by all its
+    /// contexts.
+    #[inline]
+    pub fn is_joined(&self) -> bool {
+
+        #[cfg(not(feature = "ctx-send"))]
+        {
+            THREAD_JOINED.with(Cell::get)
+        }
+
+        #[cfg(feature = "ctx-send")]
+        {
+            self.joined.get()
+        }
+    }
+
+    fn set_joined(&self, joined: bool) {
+
+        #[cfg(not(feature = "ctx-send"))]
+        {
+            THREAD_JOINED.with(|j| j.set(joined));
+        }
+
+        #[cfg(feature = "ctx-send")]
+        {
+            self.joined.set(joined);
+        }
+    }
+
+    /// Shall drop of the joined context terminate the session (tpterm)?
+    /// Enabled by default for contexts created with `new()`. Only the context
+    /// which performed tpinit terminates the session, other contexts sharing
+    /// the thread's session (no "ctx-send") leave it joined.
+    #[inline]
+    pub fn set_term_on_drop(&mut self, term_on_drop: bool) {
+        self.term_on_drop = term_on_drop;
+    }
+
+    /// Fail with TPEPROTO unless joined, for session-only calls (tpcall...).
+    pub(crate) fn ensure_joined(&self) -> AtmiResult<()> {
+        if self.is_joined() {
             Ok(())
         } else {
-            Err(self.atmi_last_error())
+            Err(AtmiError::new(
+                raw::TPEPROTO,
+                "context has not joined the application (tpinit)",
+            ))
         }
     }
 
     /// Return last ATMI error for the current thread/context.
     pub fn atmi_last_error(&self) -> AtmiError {
-        unsafe {
+        self.with_ctx(|| unsafe {
             // Adjust types to your actual FFI signatures.
             let err_ptr = raw::_exget_tperrno_addr(); // *const i32 or *mut i32
             let code = *err_ptr;
             let msg_ptr = raw::tpstrerror(code);      // *const c_char
             let message = CStr::from_ptr(msg_ptr).to_string_lossy().into_owned();
+
+            #[cfg(feature = "ctx-send")]
+            if code as u32 == raw::TPESYSTEM || code as u32 == raw::TPEPROTO {
+                self.fault.set(true);
+            }
+
             AtmiError::new(code as u32, message)
-        }
+        })
+    }
+
+    /// Did a call fail with TPESYSTEM or TPEPROTO since the last check?
+    #[cfg(feature = "ctx-send")]
+    pub(crate) fn take_fault(&self) -> bool {
+        self.fault.replace(false)
     }
 
     /// Return last UBF error for the current thread/context.
     pub fn ubf_last_error(&self) -> UbfError {
-        unsafe {
+        self.with_ctx(|| unsafe {
             // Adjust types to your actual FFI signatures.
             let err_ptr = raw::ndrx_Bget_Ferror_addr(); // *const i32 or *mut i32
             let code = *err_ptr;
             let msg_ptr = raw::Bstrerror(code);      // *const c_char
             let message = CStr::from_ptr(msg_ptr).to_string_lossy().into_owned();
             UbfError::new(code as u32, message)
-        }
+        })
     }
 
     /// Return last Nerror for the current thread/context.
     pub fn nstd_last_error(&self) -> NstdError {
-        unsafe {
+        self.with_ctx(|| unsafe {
             // Adjust types to your actual FFI signatures.
             let err_ptr = raw::_Nget_Nerror_addr(); // *const i32 or *mut i32
             let code = *err_ptr;
             let msg_ptr = raw::Nstrerror(code);      // *const c_char
             let message = CStr::from_ptr(msg_ptr).to_string_lossy().into_owned();
             NstdError::new(code as u32, message)
-        }
+        })
     }
 
     /// Generic tpalloc -> lifetime-tied `TypedBuffer<'ctx>`.
@@ -138,20 +389,22 @@ impl AtmiCtx {
         let subtype_c = CString::new(subtype)
             .map_err(|_| AtmiError::new(raw::TPEINVAL, "subtype contains NUL byte"))?;
 
-        let ptr = unsafe {
-            raw::tpalloc(
-                type_c.as_ptr() as *mut c_char,
-                subtype_c.as_ptr() as *mut c_char,
-                size as c_long,
-            )
-        };
+        self.with_ctx(|| {
+            let ptr = unsafe {
+                raw::tpalloc(
+                    type_c.as_ptr() as *mut c_char,
+                    subtype_c.as_ptr() as *mut c_char,
+                    size as c_long,
+                )
+            };
 
-        if ptr.is_null() {
-            Err(self.atmi_last_error())
-        } else {
-            let buf = unsafe { TypedBuffer::from_raw(self, ptr) };
-            Ok(buf)
-        }
+            if ptr.is_null() {
+                Err(self.atmi_last_error())
+            } else {
+                let buf = unsafe { TypedBuffer::from_raw(self, ptr) };
+                Ok(buf)
+            }
+        })
     }
 
     /// Typed helper: UBF buffer.
@@ -159,20 +412,22 @@ impl AtmiCtx {
         let type_c = CString::new("UBF").unwrap();
         let subtype_c = CString::new("").unwrap();
 
-        let raw_ptr = unsafe {
-            raw::tpalloc(
-                type_c.as_ptr() as *mut c_char,
-                subtype_c.as_ptr() as *mut c_char,
-                size as c_long,
-            )
-        };
+        self.with_ctx(|| {
+            let raw_ptr = unsafe {
+                raw::tpalloc(
+                    type_c.as_ptr() as *mut c_char,
+                    subtype_c.as_ptr() as *mut c_char,
+                    size as c_long,
+                )
+            };
 
-        if raw_ptr.is_null() {
-            Err(self.atmi_last_error())
-        } else {
-            let ubf = unsafe { TypedUbf::from_raw(self, raw_ptr) };
-            Ok(ubf)
-        }
+            if raw_ptr.is_null() {
+                Err(self.atmi_last_error())
+            } else {
+                let ubf = unsafe { TypedUbf::from_raw(self, raw_ptr) };
+                Ok(ubf)
+            }
+        })
     }
 
     /*
@@ -181,8 +436,30 @@ impl AtmiCtx {
     */
 }
 
+/// Undoes `switch_in` of `with_ctx` on drop, also when unwinding.
+#[cfg(feature = "ctx-send")]
+struct SwitchGuard<'a> {
+    ctx: &'a AtmiCtx,
+    prev: Option<CtxHandle>,
+}
+
+#[cfg(feature = "ctx-send")]
+impl Drop for SwitchGuard<'_> {
+    fn drop(&mut self) {
+        self.ctx.switch_out(self.prev.take());
+    }
+}
+
 impl Drop for AtmiCtx {
     fn drop(&mut self) {
-        unsafe { raw::tpterm(); }
+        if self.term_on_drop && self.inited.get() && self.is_joined() {
+            self.with_ctx(|| unsafe { raw::tpterm(); });
+            self.set_joined(false);
+        }
+
+        #[cfg(feature = "ctx-send")]
+        if self.owns_handle {
+            unsafe { raw::tpfreectxt(self.handle) };
+        }
     }
 }
@@ -0,0 +1,244 @@
+// src/atmictx_call.rs
+use core::ffi::{c_char, c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, CallDescriptor, CallError, CallFlags, CallOptions,
+    CallResult, TypedBuffer, TypedUbf};
+use crate::ctx_guard::check_buffer_ctx;
+
+use std::{
+    ffi::CString,
+    ptr,
+};
+
+/// Convert service name to C string.
+pub(crate) fn service_cstr(service: &str) -> AtmiResult<CString> {
+    CString::new(service)
+        .map_err(|_| AtmiError::new(raw::TPEINVAL, "service contains NUL byte"))
+}
+
+impl AtmiCtx {
+
+    /// Allocate reply buffer of the same type/sub-type and size as `idata`.
+    /// For NULL input buffer, NULL reply buffer is returned.
+    fn reply_buffer_for<'ctx>(&'ctx self, idata: &TypedBuffer<'ctx>) -> AtmiResult<TypedBuffer<'ctx>> {
+        if idata.as_ptr().is_null() {
+            return Ok(unsafe { TypedBuffer::from_raw(self, ptr::null_mut()) });
+        }
+
+        let (type_, subtype, size) = idata.tptypes()?;
+        self.tpalloc(&type_, &subtype, size)
+    }
+
+    /// Synchronous service call (tpcall).
+    ///
+    /// The reply buffer is pre-allocated with the type of `idata`. XATMI may
+    /// reallocate it or change its type (unless `TPNOCHANGE` is given), the
+    /// returned buffer always reflects the final pointer. Context must have
+    /// joined the application (`tpinit`), otherwise `TPEPROTO` is returned.
+    /// See *tpcall(3)* for more details.
+    ///
+    /// # Parameters
+    ///
+    /// * `service` – name of the service to call.
+    /// * `idata` – request buffer. It is not consumed and may be reused.
+    /// * `flags` – call flags.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(buf)` – reply buffer, `tpurcode()` gives the user return code.
+    /// * `Err(e)` – error of the call, with reply buffer and user return
+    ///   code for `TPESVCFAIL`.
+    pub fn tpcall<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        flags: CallFlags,
+    ) -> CallResult<'ctx, TypedBuffer<'ctx>> {
+        self.tpcall_with(service, idata, flags, None)
+    }
+
+    /// `tpcall` with the per-call settings of `opts`, if any.
+    pub(crate) fn tpcall_with<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        flags: CallFlags,
+        opts: Option<&CallOptions>,
+    ) -> CallResult<'ctx, TypedBuffer<'ctx>> {
+        let early = |e: AtmiError| CallError::from(e).with_service(service);
+
+        self.ensure_joined().map_err(early)?;
+        let service_c = service_cstr(service).map_err(early)?;
+        check_buffer_ctx(self, idata.ctx).map_err(early)?;
+        let mut odata = self.reply_buffer_for(idata).map_err(early)?;
+        let _info = self.prepare_call(idata.as_ptr(), opts).map_err(early)?;
+        let mut olen: c_long = 0;
+
+        let rc = self.with_ctx(|| {
+            let rc = unsafe {
+                raw::tpcall(
+                    service_c.as_ptr() as *mut c_char,
+                    idata.as_ptr(),
+                    0,
+                    odata.ptr_mut(),
+                    &mut olen,
+                    flags.bits(),
+                )
+            };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        });
+
+        // odata may have been reallocated, also on error
+        match rc {
+            Ok(()) => Ok(odata),
+            Err(e) => Err(CallError::new(self, e, service, odata, olen)),
+        }
+    }
+
+    /// Synchronous service call (tpcall) with UBF request and reply.
+    ///
+    /// If the service replies with non-UBF buffer, `TPEOTYPE` is returned.
+    /// The failure reply is kept as is, see `CallError::take_reply_ubf`.
+    /// See *tpcall(3)* for more details.
+    pub fn tpcall_ubf<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedUbf<'ctx>,
+        flags: CallFlags,
+    ) -> CallResult<'ctx, TypedUbf<'ctx>> {
+        let reply = self.tpcall(service, idata, flags)?;
+        ubf_reply(reply).map_err(|e| CallError::from(e).with_service(service))
+    }
+
+    /// Asynchronous service call (tpacall).
+    ///
+    /// Reply is collected with `CallDescriptor::reply()`, or with `getrply_any()`.
+    /// Dropping the descriptor before the reply is collected cancels the call.
+    /// See *tpacall(3)* for more details.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(cd))` – call descriptor for the reply.
+    /// * `Ok(None)` – if `TPNOREPLY` was given, no reply is expected.
+    /// * `Err(e)` – ATMI error of the call.
+    pub fn tpacall<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        flags: CallFlags,
+    ) -> AtmiResult<Option<CallDescriptor<'ctx>>> {
+        self.tpacall_with(service, idata, flags, None)
+    }
+
+    /// `tpacall` with the per-call settings of `opts`, if any.
+    pub(crate) fn tpacall_with<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        flags: CallFlags,
+        opts: Option<&CallOptions>,
+    ) -> AtmiResult<Option<CallDescriptor<'ctx>>> {
+        self.ensure_joined()?;
+        let service_c = service_cstr(service)?;
+        check_buffer_ctx(self, idata.ctx)?;
+        let _info = self.prepare_call(idata.as_ptr(), opts)?;
+
+        let cd = self.with_ctx(|| {
+            let cd = unsafe {
+                raw::tpacall(
+                    service_c.as_ptr() as *mut c_char,
+                    idata.as_ptr(),
+                    0,
+                    flags.bits(),
+                )
+            };
+
+            if cd == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(cd)
+            }
+        })?;
+
+        if flags.contains(CallFlags::TPNOREPLY) {
+            Ok(None)
+        } else {
+            Ok(Some(CallDescriptor::new(self, cd, service)))
+        }
+    }
+
+    /// Wait for the reply of any outstanding call (tpgetrply with TPGETANY).
+    ///
+    /// If the reply belongs to a live `CallDescriptor`, that descriptor is
+    /// marked as completed and will not be cancelled on drop.
+    /// See *tpgetrply(3)* for more details.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((cd, buf))` – call descriptor number and its reply buffer.
+    /// * `Err(e)` – error of the call; for failed replies (e.g. `TPESVCFAIL`)
+    ///   with the call descriptor, service name, reply buffer and user return code.
+    pub fn getrply_any<'ctx>(&'ctx self, flags: CallFlags) -> CallResult<'ctx, (i32, TypedBuffer<'ctx>)> {
+        self.ensure_joined()?;
+        self.apply_wait_deadline()?;
+
+        let mut cd: c_int = raw::EXFAIL as c_int;
+        let mut odata = unsafe { TypedBuffer::from_raw(self, ptr::null_mut()) };
+        let mut olen: c_long = 0;
+
+        let rc = self.with_ctx(|| {
+            let rc = unsafe {
+                raw::tpgetrply(
+                    &mut cd,
+                    odata.ptr_mut(),
+                    &mut olen,
+                    flags.bits() | raw::TPGETANY as c_long,
+                )
+            };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        });
+
+        // with TPGETANY, service failures still report the descriptor
+        let service = if cd != raw::EXFAIL as c_int {
+            self.calls.complete(cd)
+        } else {
+            None
+        };
+
+        match rc {
+            Ok(()) => Ok((cd, odata)),
+            Err(e) => {
+                let err = CallError::new(self, e, service.unwrap_or_default(), odata, olen);
+                Err(if cd != raw::EXFAIL as c_int { err.with_cd(cd) } else { err })
+            }
+        }
+    }
+
+    /// User return code of the last reply (tpurcode), as set by the service
+    /// in *tpreturn(3)*. See *tpurcode(3)* for more details.
+    pub fn tpurcode(&self) -> i64 {
+        self.with_ctx(|| unsafe { *raw::_exget_tpurcode_addr() })
+    }
+}
+
+/// Cast reply buffer to UBF, verifying the type.
+pub(crate) fn ubf_reply(reply: TypedBuffer<'_>) -> AtmiResult<TypedUbf<'_>> {
+    let (type_, _, _) = reply.tptypes()?;
+
+    match type_.as_str() {
+        "UBF" | "FML" | "FML32" => Ok(TypedUbf::from_typed(reply)),
+        other => Err(AtmiError::new(
+            raw::TPEOTYPE,
+            format!("expected UBF reply buffer, got [{other}]"),
+        )),
+    }
+}
@@ -33,17 +33,17 @@ fn call_logex(
 impl AtmiCtx {
     #[inline]
     pub fn tplog_str(&self, level: LogLevel, file: &'static str, line: u32, msg: &str) {
-        call_logex(raw::tplogex, level, file, line, msg);
+        self.with_ctx(|| call_logex(raw::tplogex, level, file, line, msg));
     }
 
     #[inline]
     pub fn ndrxlog_str(&self, level: LogLevel, file: &'static str, line: u32, msg: &str) {
-        call_logex(raw::ndrxlogex, level, file, line, msg);
+        self.with_ctx(|| call_logex(raw::ndrxlogex, level, file, line, msg));
     }
 
     #[inline]
     pub fn ubflog_str(&self, level: LogLevel, file: &'static str, line: u32, msg: &str) {
-        call_logex(raw::ubflogex, level, file, line, msg);
+        self.with_ctx(|| call_logex(raw::ubflogex, level, file, line, msg));
     }
 }
 
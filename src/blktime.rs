@@ -0,0 +1,94 @@
+// src/blktime.rs
+use core::ffi::{c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+
+use std::time::Duration;
+
+/// Which calls the blocking timeout applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlkTimeScope {
+    /// Next XATMI call of the context only (TPBLK_NEXT).
+    Next,
+    /// All following calls of the context (TPBLK_ALL).
+    All,
+}
+
+impl BlkTimeScope {
+    fn flags(self) -> c_long {
+        match self {
+            BlkTimeScope::Next => raw::TPBLK_NEXT as c_long,
+            BlkTimeScope::All => raw::TPBLK_ALL as c_long,
+        }
+    }
+}
+
+/// Timeout in whole seconds, rounded up. Zero stays zero (default timeout).
+fn timeout_secs(timeout: Duration) -> AtmiResult<c_int> {
+    let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+
+    c_int::try_from(secs).map_err(|_| {
+        AtmiError::new(raw::TPEINVAL, format!("invalid blocking timeout {timeout:?}"))
+    })
+}
+
+/// Blocking timeout set by `AtmiCtx::blktime_scope`, the previous value
+/// of the scope is restored on drop.
+#[derive(Debug)]
+pub struct BlkTimeGuard<'ctx> {
+    ctx: &'ctx AtmiCtx,
+    scope: BlkTimeScope,
+    prev: Duration,
+}
+
+impl Drop for BlkTimeGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.ctx.tpsblktime(self.prev, self.scope);
+    }
+}
+
+impl AtmiCtx {
+    /// Set blocking timeout of the calls (tpsblktime). The timeout is rounded
+    /// up to whole seconds, zero restores the default (`NDRX_TOUT`).
+    /// See *tpsblktime(3)* for more details.
+    pub fn tpsblktime(&self, timeout: Duration, scope: BlkTimeScope) -> AtmiResult<()> {
+        self.ensure_joined()?;
+        let secs = timeout_secs(timeout)?;
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpsblktime(secs, raw::TPBLK_SECOND as c_long | scope.flags()) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Get blocking timeout set for the scope (tpgblktime), zero if not set.
+    /// See *tpgblktime(3)* for more details.
+    pub fn tpgblktime(&self, scope: BlkTimeScope) -> AtmiResult<Duration> {
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpgblktime(raw::TPBLK_SECOND as c_long | scope.flags()) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(Duration::from_secs(rc as u64))
+            }
+        })
+    }
+
+    /// Set blocking timeout until the guard is dropped, then restore the
+    /// previous one. With `BlkTimeScope::Next` it covers the next call only.
+    pub fn blktime_scope(
+        &self,
+        timeout: Duration,
+        scope: BlkTimeScope,
+    ) -> AtmiResult<BlkTimeGuard<'_>> {
+        let prev = self.tpgblktime(scope)?;
+        self.tpsblktime(timeout, scope)?;
+
+        Ok(BlkTimeGuard { ctx: self, scope, prev })
+    }
+}
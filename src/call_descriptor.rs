@@ -0,0 +1,166 @@
+// src/call_descriptor.rs
+use core::ffi::{c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, CallError, CallFlags, CallResult, TypedBuffer};
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ptr,
+};
+
+/// Per-context book keeping of call descriptors with outstanding replies.
+///
+/// XATMI reuses descriptor numbers once the reply is collected, thus each
+/// `tpacall` gets a sequence number. A `CallDescriptor` only cancels its call
+/// if the descriptor is still registered with the same sequence, i.e. reply
+/// was not collected by `getrply_any()` and the number was not reused.
+#[derive(Debug, Default)]
+pub(crate) struct CallRegistry {
+    seq: Cell<u64>,
+    pending: RefCell<HashMap<c_int, (u64, String)>>,
+}
+
+impl CallRegistry {
+    /// Register new outstanding call, returns its sequence number.
+    pub(crate) fn register(&self, cd: c_int, service: &str) -> u64 {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        self.pending.borrow_mut().insert(cd, (seq, service.to_owned()));
+        seq
+    }
+
+    /// Is the call `cd`/`seq` still waiting for reply?
+    pub(crate) fn is_pending(&self, cd: c_int, seq: u64) -> bool {
+        self.pending.borrow().get(&cd).is_some_and(|(s, _)| *s == seq)
+    }
+
+    /// Name of the service called with `cd`, empty if not registered.
+    pub(crate) fn service(&self, cd: c_int) -> String {
+        self.pending.borrow().get(&cd).map(|(_, s)| s.clone()).unwrap_or_default()
+    }
+
+    /// Number of calls waiting for reply.
+    pub(crate) fn outstanding(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Descriptor is no more valid (reply received or call cancelled).
+    /// Returns the name of the called service.
+    pub(crate) fn complete(&self, cd: c_int) -> Option<String> {
+        self.pending.borrow_mut().remove(&cd).map(|(_, service)| service)
+    }
+}
+
+/// Errors after which the call descriptor is still valid for tpgetrply.
+fn keeps_descriptor(err: &AtmiError) -> bool {
+    matches!(err.code, AtmiError::TPEBLOCK | AtmiError::TPETIME | AtmiError::TPGOTSIG)
+}
+
+/// Handle of the asynchronous call made by `AtmiCtx::tpacall`.
+///
+/// If the handle is dropped before the reply is collected, the call is
+/// cancelled with *tpcancel(3)*, so that descriptors are not leaked.
+#[derive(Debug)]
+pub struct CallDescriptor<'ctx> {
+    ctx: &'ctx AtmiCtx,
+    cd: c_int,
+    seq: u64,
+}
+
+impl<'ctx> CallDescriptor<'ctx> {
+    pub(crate) fn new(ctx: &'ctx AtmiCtx, cd: c_int, service: &str) -> Self {
+        let seq = ctx.calls.register(cd, service);
+        CallDescriptor { ctx, cd, seq }
+    }
+
+    /// Name of the called service.
+    pub fn service(&self) -> String {
+        self.ctx.calls.service(self.cd)
+    }
+
+    /// XATMI call descriptor number.
+    #[inline]
+    pub fn cd(&self) -> i32 {
+        self.cd
+    }
+
+    /// Is the reply still outstanding?
+    pub fn is_pending(&self) -> bool {
+        self.ctx.calls.is_pending(self.cd, self.seq)
+    }
+
+    /// Wait for the reply of this call (tpgetrply).
+    ///
+    /// The descriptor stays valid after `TPEBLOCK`, `TPETIME` and `TPGOTSIG`,
+    /// so `reply()` may be retried. Failed replies carry the reply buffer
+    /// and user return code, see `CallError`. See *tpgetrply(3)* for more details.
+    pub fn reply(&mut self, flags: CallFlags) -> CallResult<'ctx, TypedBuffer<'ctx>> {
+        if !self.is_pending() {
+            return Err(AtmiError::new(
+                raw::TPEBADDESC,
+                "reply already collected or call cancelled",
+            )
+            .into());
+        }
+
+        self.ctx.apply_wait_deadline()?;
+
+        let mut cd = self.cd;
+        let mut odata = unsafe { TypedBuffer::from_raw(self.ctx, ptr::null_mut()) };
+        let mut olen: c_long = 0;
+
+        let ctx = self.ctx;
+        let rc = ctx.with_ctx(|| {
+            let rc = unsafe { raw::tpgetrply(&mut cd, odata.ptr_mut(), &mut olen, flags.bits()) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(ctx.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        });
+
+        match rc {
+            Ok(()) => {
+                self.ctx.calls.complete(self.cd);
+                Ok(odata)
+            }
+            Err(err) => {
+                let service = if keeps_descriptor(&err) {
+                    self.ctx.calls.service(self.cd)
+                } else {
+                    self.ctx.calls.complete(self.cd).unwrap_or_default()
+                };
+                Err(CallError::new(self.ctx, err, service, odata, olen).with_cd(self.cd))
+            }
+        }
+    }
+
+    /// Cancel the call (tpcancel). See *tpcancel(3)* for more details.
+    pub fn cancel(mut self) -> AtmiResult<()> {
+        self.cancel_pending()
+    }
+
+    fn cancel_pending(&mut self) -> AtmiResult<()> {
+        if !self.is_pending() {
+            return Ok(());
+        }
+
+        self.ctx.calls.complete(self.cd);
+
+        self.ctx.with_ctx(|| {
+            let rc = unsafe { raw::tpcancel(self.cd) };
+            if rc == raw::EXFAIL as c_int {
+                Err(self.ctx.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+impl<'ctx> Drop for CallDescriptor<'ctx> {
+    fn drop(&mut self) {
+        let _ = self.cancel_pending();
+    }
+}
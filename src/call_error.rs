@@ -0,0 +1,104 @@
+// src/call_error.rs
+use core::ffi::c_long;
+use crate::{AtmiCtx, AtmiError, AtmiResult, TypedBuffer, TypedUbf};
+use crate::atmictx_call::ubf_reply;
+
+use std::{error::Error, fmt, ops::Deref};
+
+/// Failed service call (tpcall, tpgetrply).
+///
+/// Besides the ATMI error it carries what the service sent back with
+/// TPFAIL: the reply buffer and the user return code (`tpurcode`).
+/// Dereferences to `AtmiError`, e.g. `err.code`.
+#[derive(Debug)]
+pub struct CallError<'ctx> {
+    /// ATMI error of the call.
+    pub error: AtmiError,
+    /// Name of the called service, empty if not known.
+    pub service: String,
+    /// User return code of the reply (tpurcode).
+    pub urcode: i64,
+    /// Reply buffer, given for `TPESVCFAIL` if the service sent data
+    /// (returned length is not zero).
+    pub reply: Option<TypedBuffer<'ctx>>,
+    /// Call descriptor, for asynchronous calls.
+    pub cd: Option<i32>,
+}
+
+/// Result of the service call.
+pub type CallResult<'ctx, T> = Result<T, CallError<'ctx>>;
+
+impl<'ctx> CallError<'ctx> {
+    /// Build from failed call on `ctx`, reply buffer of length `len` is kept
+    /// for TPESVCFAIL. The pre-allocated reply buffer is dropped if the
+    /// service sent no data.
+    pub(crate) fn new(
+        ctx: &'ctx AtmiCtx,
+        error: AtmiError,
+        service: impl Into<String>,
+        reply: TypedBuffer<'ctx>,
+        len: c_long,
+    ) -> Self {
+        let reply = (error.code == AtmiError::TPESVCFAIL && !reply.as_ptr().is_null() && len > 0)
+            .then_some(reply);
+
+        CallError { urcode: ctx.tpurcode(), error, service: service.into(), reply, cd: None }
+    }
+
+    pub(crate) fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = service.into();
+        self
+    }
+
+    pub(crate) fn with_cd(mut self, cd: i32) -> Self {
+        self.cd = Some(cd);
+        self
+    }
+
+    /// Did the service reply with TPFAIL?
+    pub fn is_service_failure(&self) -> bool {
+        self.error.code == AtmiError::TPESVCFAIL
+    }
+
+    /// Take the reply buffer as UBF, `TPEOTYPE` if of other type.
+    pub fn take_reply_ubf(&mut self) -> Option<AtmiResult<TypedUbf<'ctx>>> {
+        self.reply.take().map(ubf_reply)
+    }
+}
+
+impl Deref for CallError<'_> {
+    type Target = AtmiError;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.error
+    }
+}
+
+impl fmt::Display for CallError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.service.is_empty() {
+            write!(f, "{} (urcode {})", self.error, self.urcode)
+        } else {
+            write!(f, "service [{}]: {} (urcode {})", self.service, self.error, self.urcode)
+        }
+    }
+}
+
+impl Error for CallError<'_> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<CallError<'_>> for AtmiError {
+    fn from(e: CallError<'_>) -> Self {
+        e.error
+    }
+}
+
+impl From<AtmiError> for CallError<'_> {
+    fn from(error: AtmiError) -> Self {
+        CallError { error, service: String::new(), urcode: 0, reply: None, cd: None }
+    }
+}
@@ -0,0 +1,161 @@
+// src/call_options.rs
+use core::ffi::c_char;
+use crate::deadline::CallInfoGuard;
+use crate::{AtmiCtx, AtmiResult, BlkTimeScope, CallDescriptor, CallError, CallFlags, CallResult,
+    Priority, RetryPolicy, TypedBuffer};
+
+use std::{thread, time::Duration};
+
+/// Settings of a single service call, see `AtmiCtx::tpcall_opts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CallOptions {
+    /// Call flags.
+    pub flags: CallFlags,
+    /// Blocking timeout of this call, default timeout if `None`. Rounded up
+    /// to whole seconds, as *tpsblktime(3)* takes seconds.
+    pub timeout: Option<Duration>,
+    /// Priority of this call, default priority of the service if `None`.
+    pub priority: Option<Priority>,
+    /// Retry of failed `tpcall_opts`, no retries if `None`.
+    pub retry: Option<RetryPolicy>,
+}
+
+impl CallOptions {
+    /// Options with no flags and the default timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set call flags.
+    pub fn flags(mut self, flags: CallFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set blocking timeout of the call (rounded up to whole seconds).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set priority of the call.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Retry failed synchronous call according to `policy`.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Apply per-call settings to the context, right before the call.
+    fn apply(&self, ctx: &AtmiCtx) -> AtmiResult<()> {
+        if let Some(timeout) = self.timeout {
+            ctx.tpsblktime(timeout, BlkTimeScope::Next)?;
+        }
+
+        if let Some(priority) = self.priority {
+            if let Err(e) = ctx.tpsprio(priority) {
+                self.reset(ctx);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Undo `apply` when the call is not made, so that the settings do not
+    /// go to the next call of the context.
+    fn reset(&self, ctx: &AtmiCtx) {
+        if self.timeout.is_some() {
+            let _ = ctx.tpsblktime(Duration::ZERO, BlkTimeScope::Next);
+        }
+
+        if self.priority.is_some() {
+            let _ = ctx.tpsprio(Priority::Relative(0));
+        }
+    }
+}
+
+impl AtmiCtx {
+    /// Set `opts` and the deadline for the call about to send `data`, after
+    /// all other checks of the call passed. On error nothing stays set.
+    /// The returned guard shall be kept until the call is made.
+    pub(crate) fn prepare_call(
+        &self,
+        data: *mut c_char,
+        opts: Option<&CallOptions>,
+    ) -> AtmiResult<Option<CallInfoGuard<'_>>> {
+        if let Some(opts) = opts {
+            opts.apply(self)?;
+        }
+
+        self.apply_deadline(data).inspect_err(|_| {
+            if let Some(opts) = opts {
+                opts.reset(self);
+            }
+        })
+    }
+
+    /// Synchronous service call (tpcall) with per-call options.
+    ///
+    /// Failed call is retried according to `opts.retry`, except in a global
+    /// transaction (unless `TPNOTRAN`). With circuit breaker set on the
+    /// context, calls to a service with open circuit fail with `TPELIMIT`.
+    /// See `tpcall` and *tpcall(3)* for more details.
+    pub fn tpcall_opts<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        opts: &CallOptions,
+    ) -> CallResult<'ctx, TypedBuffer<'ctx>> {
+        let policy = opts.retry.unwrap_or_else(|| RetryPolicy::new().max_attempts(1));
+
+        // retried request could be processed twice in the transaction
+        let attempts = if policy.attempts() > 1
+            && !opts.flags.contains(CallFlags::TPNOTRAN)
+            && self.tpgetlev().unwrap_or(true)
+        {
+            1
+        } else {
+            policy.attempts()
+        };
+
+        if let Some(breaker) = &self.breaker {
+            breaker.check(service).map_err(|e| CallError::from(e).with_service(service))?;
+        }
+
+        // breaker counts the call once, with the result of the last attempt
+        let mut attempt = 1;
+        let rc = loop {
+            match self.tpcall_with(service, idata, opts.flags, Some(opts)) {
+                Err(e) if attempt < attempts && policy.is_retryable(e.code) => {
+                    crate::tp_warn!(self, "Call of [{}] failed (attempt {}/{}), retrying: {}",
+                        service, attempt, attempts, e);
+                    thread::sleep(policy.backoff_for(attempt));
+                    attempt += 1;
+                }
+                rc => break rc,
+            }
+        };
+
+        if let Some(breaker) = &self.breaker {
+            breaker.record(service, rc.as_ref().err().map(|e| &e.error));
+        }
+
+        rc
+    }
+
+    /// Asynchronous service call (tpacall) with per-call options.
+    /// See `tpacall` and *tpacall(3)* for more details.
+    pub fn tpacall_opts<'ctx>(
+        &'ctx self,
+        service: &str,
+        idata: &mut TypedBuffer<'ctx>,
+        opts: &CallOptions,
+    ) -> AtmiResult<Option<CallDescriptor<'ctx>>> {
+        self.tpacall_with(service, idata, opts.flags, Some(opts))
+    }
+}
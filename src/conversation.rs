@@ -0,0 +1,244 @@
+// src/conversation.rs
+use core::ffi::{c_char, c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, CallFlags, TypedBuffer};
+use crate::atmictx_call::service_cstr;
+use crate::ctx_guard::check_buffer_ctx;
+
+use std::ptr;
+
+/// Conversation event, reported by XATMI as TPEEVENT error + `revent`.
+/// See *tpsend(3)* and *tprecv(3)* for more details.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConvEvent {
+    /// TPEV_SENDONLY – the other side gave control, we may send now.
+    SendOnly,
+    /// TPEV_SVCSUCC – service returned with TPSUCCESS.
+    SvcSucc,
+    /// TPEV_SVCFAIL – service returned with TPFAIL.
+    SvcFail,
+    /// TPEV_DISCONIMM – the other side disconnected.
+    DisconImm,
+    /// TPEV_SVCERR – service failed (e.g. returned without tpreturn).
+    SvcErr,
+}
+
+impl ConvEvent {
+    fn from_revent(revent: c_long) -> Option<Self> {
+        match revent as u32 {
+            raw::TPEV_SENDONLY => Some(ConvEvent::SendOnly),
+            raw::TPEV_SVCSUCC => Some(ConvEvent::SvcSucc),
+            raw::TPEV_SVCFAIL => Some(ConvEvent::SvcFail),
+            raw::TPEV_DISCONIMM => Some(ConvEvent::DisconImm),
+            raw::TPEV_SVCERR => Some(ConvEvent::SvcErr),
+            _ => None,
+        }
+    }
+
+    /// Does this event end the conversation?
+    #[inline]
+    pub fn is_final(self) -> bool {
+        self != ConvEvent::SendOnly
+    }
+}
+
+/// Open conversation, either started by `AtmiCtx::tpconnect` (initiator),
+/// or received by the TPCONV service (`TpSvcInfo::conversation`).
+///
+/// Dropping the initiator's conversation which is not finished yet, performs
+/// *tpdiscon(3)*. Service side never disconnects, it ends with the service reply.
+#[derive(Debug)]
+pub struct Conversation<'ctx> {
+    ctx: &'ctx AtmiCtx,
+    cd: c_int,
+    open: bool,
+    initiator: bool,
+}
+
+impl<'ctx> Conversation<'ctx> {
+    /// Service side of the conversation, `cd` comes from TPSVCINFO.
+    pub(crate) fn from_service(ctx: &'ctx AtmiCtx, cd: c_int) -> Self {
+        Conversation { ctx, cd, open: true, initiator: false }
+    }
+
+    /// XATMI conversation descriptor number.
+    #[inline]
+    pub fn cd(&self) -> i32 {
+        self.cd
+    }
+
+    /// Is the conversation still running (no final event received)?
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Resolve failed tpsend/tprecv: TPEEVENT is mapped to the event.
+    fn event_or_error(&mut self, err: AtmiError, revent: c_long) -> AtmiResult<ConvEvent> {
+        if err.code != AtmiError::TPEEVENT {
+            return Err(err);
+        }
+
+        let ev = ConvEvent::from_revent(revent).ok_or_else(|| {
+            AtmiError::new(raw::TPESYSTEM, format!("unknown conversation event {revent}"))
+        })?;
+
+        if ev.is_final() {
+            self.open = false;
+        }
+
+        Ok(ev)
+    }
+
+    /// Send data to the other side (tpsend).
+    /// See *tpsend(3)* for more details.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` – data sent.
+    /// * `Ok(Some(ev))` – data not sent, event received instead.
+    /// * `Err(e)` – ATMI error of the call.
+    pub fn send(
+        &mut self,
+        data: &mut TypedBuffer<'ctx>,
+        flags: CallFlags,
+    ) -> AtmiResult<Option<ConvEvent>> {
+        self.ctx.ensure_joined()?;
+        check_buffer_ctx(self.ctx, data.ctx)?;
+        self.ctx.apply_wait_deadline()?;
+        let mut revent: c_long = 0;
+
+        let ctx = self.ctx;
+        let rc = ctx.with_ctx(|| {
+            let rc = unsafe { raw::tpsend(self.cd, data.as_ptr(), 0, flags.bits(), &mut revent) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(ctx.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        });
+
+        match rc {
+            Ok(()) => Ok(None),
+            Err(err) => self.event_or_error(err, revent).map(Some),
+        }
+    }
+
+    /// Receive data from the other side (tprecv).
+    /// See *tprecv(3)* for more details.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((buf, None))` – data received.
+    /// * `Ok((buf, Some(ev)))` – event received, for `SvcSucc`/`SvcFail`
+    ///   and `SendOnly` the buffer carries the data sent with it.
+    /// * `Err(e)` – ATMI error of the call.
+    pub fn recv(
+        &mut self,
+        flags: CallFlags,
+    ) -> AtmiResult<(TypedBuffer<'ctx>, Option<ConvEvent>)> {
+        self.ctx.ensure_joined()?;
+        self.ctx.apply_wait_deadline()?;
+        let mut odata = unsafe { TypedBuffer::from_raw(self.ctx, ptr::null_mut()) };
+        let mut olen: c_long = 0;
+        let mut revent: c_long = 0;
+
+        let ctx = self.ctx;
+        let rc = ctx.with_ctx(|| {
+            let rc = unsafe {
+                raw::tprecv(self.cd, odata.ptr_mut(), &mut olen, flags.bits(), &mut revent)
+            };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(ctx.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        });
+
+        match rc {
+            Ok(()) => Ok((odata, None)),
+            Err(err) => {
+                let ev = self.event_or_error(err, revent)?;
+                Ok((odata, Some(ev)))
+            }
+        }
+    }
+
+    /// Abort the conversation (tpdiscon). Only the initiator may disconnect.
+    /// See *tpdiscon(3)* for more details.
+    pub fn discon(mut self) -> AtmiResult<()> {
+        self.discon_open()
+    }
+
+    fn discon_open(&mut self) -> AtmiResult<()> {
+        if !self.open {
+            return Ok(());
+        }
+
+        if !self.initiator {
+            return Err(AtmiError::new(
+                raw::TPEPROTO,
+                "service side cannot disconnect the conversation",
+            ));
+        }
+
+        self.open = false;
+
+        self.ctx.with_ctx(|| {
+            let rc = unsafe { raw::tpdiscon(self.cd) };
+            if rc == raw::EXFAIL as c_int {
+                Err(self.ctx.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+impl<'ctx> Drop for Conversation<'ctx> {
+    fn drop(&mut self) {
+        if self.initiator {
+            let _ = self.discon_open();
+        }
+    }
+}
+
+impl AtmiCtx {
+    /// Start conversation with the service (tpconnect).
+    /// See *tpconnect(3)* for more details.
+    ///
+    /// # Parameters
+    ///
+    /// * `service` – name of the conversational service.
+    /// * `data` – optional initial data.
+    /// * `flags` – call flags, `TPSENDONLY` or `TPRECVONLY` is required.
+    pub fn tpconnect<'ctx>(
+        &'ctx self,
+        service: &str,
+        data: Option<&mut TypedBuffer<'ctx>>,
+        flags: CallFlags,
+    ) -> AtmiResult<Conversation<'ctx>> {
+        self.ensure_joined()?;
+        let service_c = service_cstr(service)?;
+        if let Some(d) = &data {
+            check_buffer_ctx(self, d.ctx)?;
+        }
+        let data_ptr = data.map_or(ptr::null_mut(), |d| d.as_ptr());
+        let _info = self.prepare_call(data_ptr, None)?;
+
+        let cd = self.with_ctx(|| {
+            let cd = unsafe {
+                raw::tpconnect(service_c.as_ptr() as *mut c_char, data_ptr, 0, flags.bits())
+            };
+
+            if cd == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(cd)
+            }
+        })?;
+
+        Ok(Conversation { ctx: self, cd, open: true, initiator: true })
+    }
+}
@@ -0,0 +1,114 @@
+// src/ctx_guard.rs
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+
+#[cfg(feature = "ctx-send")]
+use crate::atmictx::CtxHandle;
+
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    ptr,
+};
+
+thread_local! {
+    /// Context entered on this thread with `AtmiCtx::enter`, null if none.
+    static ACTIVE: Cell<*const AtmiCtx> = const { Cell::new(ptr::null()) };
+}
+
+/// Context made current for the thread by `AtmiCtx::enter`.
+///
+/// While the guard lives, the context is current for the thread, see
+/// `AtmiCtx::enter`. Dropping it restores the context which was current before.
+/// The guard is bound to the thread which created it.
+#[derive(Debug)]
+pub struct CtxGuard<'ctx> {
+    ctx: &'ctx AtmiCtx,
+
+    #[cfg(feature = "ctx-send")]
+    prev: Option<CtxHandle>,
+
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'ctx> CtxGuard<'ctx> {
+    /// Entered context.
+    #[inline]
+    pub fn ctx(&self) -> &'ctx AtmiCtx {
+        self.ctx
+    }
+}
+
+impl<'ctx> Drop for CtxGuard<'ctx> {
+    fn drop(&mut self) {
+        #[cfg(feature = "ctx-send")]
+        self.ctx.switch_out(self.prev.take());
+
+        ACTIVE.with(|a| a.set(ptr::null()));
+    }
+}
+
+/// Reject buffer of other context than `ctx` which makes the call with it,
+/// or than the context entered on the thread, if any (debug builds only).
+#[inline]
+pub(crate) fn check_buffer_ctx(ctx: &AtmiCtx, buf_ctx: &AtmiCtx) -> AtmiResult<()> {
+    #[cfg(debug_assertions)]
+    {
+        if !ptr::eq(ctx, buf_ctx) {
+            return Err(AtmiError::new(
+                raw::TPEINVAL,
+                "buffer belongs to other context than the one making the call",
+            ));
+        }
+
+        let active = ACTIVE.with(|a| a.get());
+
+        if !active.is_null() && !ptr::eq(active, buf_ctx) {
+            return Err(AtmiError::new(
+                raw::TPEINVAL,
+                "buffer belongs to other context than the entered one",
+            ));
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    let _ = (ctx, buf_ctx);
+
+    Ok(())
+}
+
+impl AtmiCtx {
+    /// Make this context current for the thread until the guard is dropped,
+    /// e.g. when the thread serves several sessions. Guards cannot be nested,
+    /// entering while other guard is alive on the thread gives `TPEPROTO`.
+    ///
+    /// Only with "ctx-send" contexts have own C contexts, which the guard
+    /// switches (tpsetctxt). Without it all contexts of the thread share the
+    /// thread's C context and the guard only marks this one as entered.
+    /// Calls of other contexts still run on their own context, but in debug
+    /// builds buffers of other contexts than the entered one are rejected
+    /// with `TPEINVAL`. See *tpsetctxt(3)* for more details.
+    pub fn enter(&self) -> AtmiResult<CtxGuard<'_>> {
+        let entered = ACTIVE.with(|a| {
+            if a.get().is_null() {
+                a.set(self as *const AtmiCtx);
+                false
+            } else {
+                true
+            }
+        });
+
+        if entered {
+            return Err(AtmiError::new(
+                raw::TPEPROTO,
+                "context is already entered on this thread",
+            ));
+        }
+
+        Ok(CtxGuard {
+            ctx: self,
+            #[cfg(feature = "ctx-send")]
+            prev: self.switch_in(),
+            _not_send: PhantomData,
+        })
+    }
+}
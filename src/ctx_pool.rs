@@ -0,0 +1,291 @@
+// src/ctx_pool.rs
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+
+use std::{
+    ops::Deref,
+    sync::{Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Context setup/check function of the pool.
+pub type PoolCtxFn = dyn Fn(&AtmiCtx) -> AtmiResult<()> + Send + Sync;
+
+/// Snapshot of the pool usage, see `AtmiCtxPool::metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolMetrics {
+    /// Configured number of contexts.
+    pub size: usize,
+    /// Contexts currently open (idle + in use).
+    pub open: usize,
+    /// Contexts lent out.
+    pub in_use: usize,
+    /// Threads waiting for a context.
+    pub waiters: usize,
+    /// Contexts re-initialized after errors or failed health checks.
+    pub reinits: u64,
+}
+
+#[derive(Default)]
+struct PoolState {
+    idle: Vec<AtmiCtx>,
+    open: usize,
+    in_use: usize,
+    waiters: usize,
+    reinits: u64,
+}
+
+/// Pool of client contexts (own *tpnewctxt(3)* each), shared by threads.
+///
+/// Contexts are joined with `init` (default *tpinit(3)*) and lent out with
+/// `CtxLease`. A context which failed with `TPESYSTEM` or `TPEPROTO` while
+/// lent is re-initialized (tpterm + init) when the lease is dropped.
+/// If a health check is set, idle contexts are checked before lending.
+pub struct AtmiCtxPool {
+    size: usize,
+    state: Mutex<PoolState>,
+    available: Condvar,
+    init: Box<PoolCtxFn>,
+    health_check: Option<Box<PoolCtxFn>>,
+}
+
+impl std::fmt::Debug for AtmiCtxPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AtmiCtxPool").field("metrics", &self.metrics()).finish()
+    }
+}
+
+impl AtmiCtxPool {
+    /// Create pool of `size` contexts, joined with plain `tpinit`.
+    pub fn new(size: usize) -> AtmiResult<Self> {
+        Self::with_init(size, |ctx: &AtmiCtx| ctx.tpinit())
+    }
+
+    /// Create pool of `size` contexts, each joined with `init`,
+    /// e.g. `|ctx| ctx.tpinit_builder().cltname("web").init()`.
+    /// All contexts are created upfront, the first failure is returned.
+    pub fn with_init<F>(size: usize, init: F) -> AtmiResult<Self>
+    where
+        F: Fn(&AtmiCtx) -> AtmiResult<()> + Send + Sync + 'static,
+    {
+        if size == 0 {
+            return Err(AtmiError::new(raw::TPEINVAL, "pool size must be positive"));
+        }
+
+        let pool = AtmiCtxPool {
+            size,
+            state: Mutex::new(PoolState::default()),
+            available: Condvar::new(),
+            init: Box::new(init),
+            health_check: None,
+        };
+
+        let mut idle = Vec::with_capacity(size);
+        for _ in 0..size {
+            idle.push(pool.open_ctx()?);
+        }
+
+        {
+            let mut state = pool.lock();
+            state.open = idle.len();
+            state.idle = idle;
+        }
+
+        Ok(pool)
+    }
+
+    /// Check idle contexts with `check` (a cheap service call, for example)
+    /// before lending them out. Failing contexts are re-initialized.
+    pub fn with_health_check<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&AtmiCtx) -> AtmiResult<()> + Send + Sync + 'static,
+    {
+        self.health_check = Some(Box::new(check));
+        self
+    }
+
+    /// Current usage of the pool.
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.lock();
+        PoolMetrics {
+            size: self.size,
+            open: state.open,
+            in_use: state.in_use,
+            waiters: state.waiters,
+            reinits: state.reinits,
+        }
+    }
+
+    /// Borrow a context, waiting until one is free.
+    pub fn acquire(&self) -> AtmiResult<CtxLease<'_>> {
+        self.acquire_until(None)
+    }
+
+    /// Borrow a context, waiting at most `timeout`, `TPETIME` if none got free.
+    pub fn acquire_timeout(&self, timeout: Duration) -> AtmiResult<CtxLease<'_>> {
+        self.acquire_until(Some(Instant::now() + timeout))
+    }
+
+    fn acquire_until(&self, deadline: Option<Instant>) -> AtmiResult<CtxLease<'_>> {
+        loop {
+            let ctx = self.take_ctx(deadline)?;
+
+            match ctx {
+                Some(ctx) => match self.checked(ctx) {
+                    Some(ctx) => return Ok(CtxLease { pool: self, ctx: Some(ctx), broken: false }),
+                    None => continue,
+                },
+                // free slot, open a replacement of the lost context
+                None => match self.open_ctx() {
+                    Ok(ctx) => return Ok(CtxLease { pool: self, ctx: Some(ctx), broken: false }),
+                    Err(e) => {
+                        self.release_slot();
+                        return Err(e);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Reserve a context: `Some(ctx)` from the idle list, or `None` for
+    /// a free slot where a new context shall be opened.
+    fn take_ctx(&self, deadline: Option<Instant>) -> AtmiResult<Option<AtmiCtx>> {
+        let mut state = self.lock();
+
+        loop {
+            if let Some(ctx) = state.idle.pop() {
+                state.in_use += 1;
+                return Ok(Some(ctx));
+            }
+
+            if state.open < self.size {
+                state.open += 1;
+                state.in_use += 1;
+                return Ok(None);
+            }
+
+            let left = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(AtmiError::new(raw::TPETIME, "no free context in the pool"));
+                    }
+                    Some(left)
+                }
+                None => None,
+            };
+
+            state.waiters += 1;
+            state = match left {
+                Some(left) => {
+                    self.available
+                        .wait_timeout(state, left)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.available.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+            state.waiters -= 1;
+        }
+    }
+
+    /// Run the health check, re-initialize on failure. `None` if the
+    /// context is lost (its slot is released).
+    fn checked(&self, ctx: AtmiCtx) -> Option<AtmiCtx> {
+        let Some(check) = &self.health_check else {
+            return Some(ctx);
+        };
+
+        if check(&ctx).is_ok() {
+            ctx.take_fault();
+            return Some(ctx);
+        }
+
+        self.reinit(ctx)
+    }
+
+    /// tpterm + init of the faulty context. `None` if it failed,
+    /// the context is closed then and its slot released.
+    fn reinit(&self, ctx: AtmiCtx) -> Option<AtmiCtx> {
+        self.lock().reinits += 1;
+
+        let _ = ctx.tpterm();
+        let rc = (self.init)(&ctx);
+        ctx.take_fault();
+
+        match rc {
+            Ok(()) => Some(ctx),
+            Err(e) => {
+                crate::tp_error!(ctx, "Pool context re-init failed: {}", e);
+                drop(ctx);
+                self.release_slot();
+                None
+            }
+        }
+    }
+
+    fn open_ctx(&self) -> AtmiResult<AtmiCtx> {
+        let ctx = AtmiCtx::new()?;
+        (self.init)(&ctx)?;
+        Ok(ctx)
+    }
+
+    fn release_slot(&self) {
+        let mut state = self.lock();
+        state.open -= 1;
+        state.in_use -= 1;
+        self.available.notify_one();
+    }
+
+    fn give_back(&self, ctx: AtmiCtx, broken: bool) {
+        let ctx = if broken || ctx.take_fault() {
+            match self.reinit(ctx) {
+                Some(ctx) => ctx,
+                None => return,
+            }
+        } else {
+            ctx
+        };
+
+        let mut state = self.lock();
+        state.in_use -= 1;
+        state.idle.push(ctx);
+        self.available.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Context lent from `AtmiCtxPool`, returned to the pool on drop.
+#[derive(Debug)]
+pub struct CtxLease<'pool> {
+    pool: &'pool AtmiCtxPool,
+    ctx: Option<AtmiCtx>,
+    broken: bool,
+}
+
+impl CtxLease<'_> {
+    /// Have the context re-initialized when returned to the pool, e.g. after
+    /// an error the pool cannot see. TPESYSTEM and TPEPROTO are detected.
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl Deref for CtxLease<'_> {
+    type Target = AtmiCtx;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.ctx.as_ref().expect("lease holds context until drop")
+    }
+}
+
+impl Drop for CtxLease<'_> {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.ctx.take() {
+            self.pool.give_back(ctx, self.broken);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// src/deadline.rs
+use core::ffi::{c_char, c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, BlkTimeScope, TypedUbf, UbfValue};
+
+use std::{
+    ptr,
+    sync::atomic::{AtomicI32, Ordering},
+    time::{Duration, Instant},
+};
+
+/// UBF field (BFLD_LONG) of the call info carrying the remaining time
+/// of the request in milliseconds, `BBADFLDID` (0) if not propagated.
+static DEADLINE_FIELD: AtomicI32 = AtomicI32::new(0);
+
+/// Propagate the request deadline to the called services in UBF field
+/// `fldid` (BFLD_LONG) of the call info (tpsetcallinfo). Global setting of
+/// the process, for all contexts; `None` stops the propagation.
+/// See *tpsetcallinfo(3)* for more details.
+pub fn set_global_deadline_field(fldid: Option<i32>) {
+    DEADLINE_FIELD.store(fldid.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Milliseconds left, zero once passed.
+fn remaining_ms(deadline: Instant) -> i64 {
+    let left = deadline.saturating_duration_since(Instant::now());
+    i64::try_from(left.as_millis()).unwrap_or(i64::MAX)
+}
+
+/// Time left until `deadline`, `TPETIME` once passed.
+fn time_left(deadline: Instant) -> AtmiResult<Duration> {
+    let left = deadline.saturating_duration_since(Instant::now());
+
+    if left.is_zero() {
+        Err(AtmiError::new(raw::TPETIME, "request deadline passed"))
+    } else {
+        Ok(left)
+    }
+}
+
+/// Read deadline from the call info of the received buffer `data`.
+///
+/// # Safety
+/// `data` must be a typed buffer received by the service, or null.
+pub(crate) unsafe fn incoming_deadline(ctx: &AtmiCtx, data: *mut c_char) -> Option<Instant> {
+    let fldid = DEADLINE_FIELD.load(Ordering::Relaxed);
+
+    if fldid == 0 || data.is_null() {
+        return None;
+    }
+
+    ctx.with_ctx(|| {
+        // no call info attached is not an error, so the ATMI error is not read
+        let mut info: *mut raw::UBFH = ptr::null_mut();
+        if raw::tpgetcallinfo(data, &mut info, 0) == raw::EXFAIL as c_int || info.is_null() {
+            return None;
+        }
+
+        // call info buffer is allocated for us, freed on drop
+        let info = TypedUbf::from_raw(ctx, info as *mut c_char);
+
+        let mut ms: c_long = 0;
+        let mut len = std::mem::size_of::<c_long>() as raw::BFLDLEN;
+        let rc = raw::CBget(
+            info.as_ubfh(),
+            fldid as raw::BFLDID,
+            0,
+            &mut ms as *mut c_long as *mut c_char,
+            &mut len,
+            raw::BFLD_LONG as c_int,
+        );
+
+        if rc == raw::EXFAIL as c_int {
+            None
+        } else {
+            Some(Instant::now() + Duration::from_millis(ms.max(0) as u64))
+        }
+    })
+}
+
+/// Call info attached to the request buffer by `apply_deadline`, removed
+/// on drop once the call is made, so that it does not go with the next
+/// call of the buffer.
+pub(crate) struct CallInfoGuard<'a> {
+    ctx: &'a AtmiCtx,
+    data: *mut c_char,
+}
+
+impl Drop for CallInfoGuard<'_> {
+    fn drop(&mut self) {
+        self.ctx.with_ctx(|| unsafe { raw::tpsetcallinfo(self.data, ptr::null_mut(), 0) });
+    }
+}
+
+impl AtmiCtx {
+    /// Set deadline of the calls made by the context, `None` to clear.
+    ///
+    /// Until the deadline the blocking timeout of each call, reply wait
+    /// (tpgetrply) and conversation send or receive is limited to the time
+    /// left (tpsblktime with TPBLK_NEXT), after it the calls fail with
+    /// `TPETIME` right away. As the blocking timeout is set in whole seconds,
+    /// the time left is rounded up and a call may wait up to 1 second past
+    /// the deadline. In a service, the deadline received from the caller is
+    /// set for the handler.
+    pub fn set_deadline(&self, deadline: Option<Instant>) {
+        self.deadline.set(deadline);
+    }
+
+    /// Deadline of the calls, see `set_deadline`.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline.get()
+    }
+
+    /// Apply the deadline to the call about to send `data`: attach the time
+    /// left as call info and limit the blocking timeout of the call. The
+    /// returned guard shall be kept until the call is made.
+    pub(crate) fn apply_deadline(&self, data: *mut c_char) -> AtmiResult<Option<CallInfoGuard<'_>>> {
+        let Some(deadline) = self.deadline.get() else {
+            return Ok(None);
+        };
+
+        let left = time_left(deadline)?;
+        let info = self.attach_deadline(data, deadline)?;
+        self.limit_next_blktime(left)?;
+
+        Ok(info)
+    }
+
+    /// Apply the deadline to the blocking call about to be made without
+    /// request, i.e. tpgetrply, tpsend or tprecv: limit its blocking timeout
+    /// to the time left, or fail with `TPETIME` once passed.
+    pub(crate) fn apply_wait_deadline(&self) -> AtmiResult<()> {
+        match self.deadline.get() {
+            Some(deadline) => self.limit_next_blktime(time_left(deadline)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Limit blocking timeout of the next call to `left`, shorter timeout
+    /// already set for it (e.g. by `tpcall_opts`) is kept.
+    fn limit_next_blktime(&self, left: Duration) -> AtmiResult<()> {
+        let next = self.tpgblktime(BlkTimeScope::Next)?;
+        if next.is_zero() || next > left {
+            self.tpsblktime(left, BlkTimeScope::Next)?;
+        }
+
+        Ok(())
+    }
+
+    /// Attach the time left until `deadline` to `data` as call info, if the
+    /// deadline field is set.
+    fn attach_deadline(&self, data: *mut c_char, deadline: Instant) -> AtmiResult<Option<CallInfoGuard<'_>>> {
+        let fldid = DEADLINE_FIELD.load(Ordering::Relaxed);
+        if fldid == 0 || data.is_null() {
+            return Ok(None);
+        }
+
+        let mut info = self.tpalloc_ubf(256)?;
+        info.bchg(fldid, 0, UbfValue::Long(remaining_ms(deadline)), true)
+            .map_err(|e| AtmiError::new(raw::TPEINVAL, e.message))?;
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpsetcallinfo(data, info.as_ubfh(), 0) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(Some(CallInfoGuard { ctx: self, data }))
+            }
+        })
+    }
+}
@@ -0,0 +1,142 @@
+// src/deferred.rs
+use core::ffi::{c_char, c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, ServiceReply};
+use crate::server::{cancel_defer, completion, finish, handled_request, with_thread_ctx, Completion};
+
+use std::ptr;
+
+/// Service request handed over to another thread, see `TpSvcInfo::defer`.
+///
+/// Holds the server context data (*tpsrvgetctxdata(3)*), the reply is sent
+/// by `complete()` from a thread which runs no service handler. Dropping the
+/// request without completing it replies with TPFAIL, so that the caller does
+/// not wait for the timeout. Dropped within the handler which deferred it,
+/// the request is answered by the dispatcher as if not deferred.
+#[derive(Debug)]
+pub struct DeferredRequest {
+    ctxdata: *mut c_char,
+    service: String,
+    restored: bool,
+    /// Id of the request, see `server::handled_request`
+    request: u64,
+}
+
+// Server context data is a plain copy of the request state; Enduro/X allows
+// to restore it in any thread of the server process.
+unsafe impl Send for DeferredRequest {}
+
+impl DeferredRequest {
+    pub(crate) fn new(ctxdata: *mut c_char, service: String, request: u64) -> Self {
+        DeferredRequest { ctxdata, service, restored: false, request }
+    }
+
+    /// Name of the service which received the request.
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// Restore the server context data in the current thread (tpsrvsetctxdata),
+    /// e.g. to call other services in the transaction of the request.
+    /// Done by `complete()` if not yet called. See *tpsrvsetctxdata(3)* for more details.
+    ///
+    /// # Errors
+    ///
+    /// * `TPEPROTO` – called from a service handler, where it would replace
+    ///   the context of the request being dispatched.
+    pub fn restore(&mut self, ctx: &AtmiCtx) -> AtmiResult<()> {
+        if handled_request().is_some() {
+            return Err(AtmiError::new(
+                raw::TPEPROTO,
+                "deferred request cannot be restored in a service handler",
+            ));
+        }
+
+        if self.restored {
+            return Ok(());
+        }
+
+        ctx.with_ctx(|| {
+            let rc = unsafe { raw::tpsrvsetctxdata(self.ctxdata, raw::SYS_SRV_THREAD as c_long) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(ctx.atmi_last_error())
+            } else {
+                self.restored = true;
+                Ok(())
+            }
+        })
+    }
+
+    /// Send the reply of the deferred request (tpreturn/tpforward).
+    ///
+    /// # Parameters
+    ///
+    /// * `ctx` – ATMI context of the current (worker) thread.
+    /// * `reply` – reply of the request; `Deferred` is answered with TPFAIL.
+    ///
+    /// # Errors
+    ///
+    /// * `TPEPROTO` – called from a service handler, see `restore`; the
+    ///   request is dropped.
+    pub fn complete(mut self, ctx: &AtmiCtx, reply: ServiceReply<'_>) -> AtmiResult<()> {
+        self.restore(ctx)?;
+
+        let reply = match completion(ctx, &self.service, Ok(reply)) {
+            Completion::Continue => {
+                crate::tp_error!(
+                    ctx,
+                    "Service [{}] deferred request completed with Deferred",
+                    self.service
+                );
+                Completion::fail()
+            }
+            reply => reply,
+        };
+
+        let ctxdata = std::mem::replace(&mut self.ctxdata, ptr::null_mut());
+        drop(self);
+
+        ctx.with_ctx(|| unsafe {
+            raw::tpsrvfreectxdata(ctxdata);
+            finish(reply);
+        });
+
+        Ok(())
+    }
+}
+
+impl Drop for DeferredRequest {
+    fn drop(&mut self) {
+        if self.ctxdata.is_null() {
+            return;
+        }
+
+        // In a handler tpreturn would not return, or would reply for the
+        // request being dispatched. The deferring handler answers its own
+        // request; one deferred by other handler is left to time out.
+        if let Some(request) = handled_request() {
+            if request == self.request {
+                cancel_defer();
+            } else {
+                with_thread_ctx(|ctx| {
+                    crate::tp_error!(
+                        ctx,
+                        "Deferred request of [{}] dropped in a service handler, not answered",
+                        self.service
+                    );
+                });
+            }
+
+            unsafe { raw::tpsrvfreectxdata(self.ctxdata) };
+            return;
+        }
+
+        unsafe {
+            if !self.restored {
+                raw::tpsrvsetctxdata(self.ctxdata, raw::SYS_SRV_THREAD as c_long);
+            }
+            raw::tpsrvfreectxdata(self.ctxdata);
+            finish(Completion::fail());
+        }
+    }
+}
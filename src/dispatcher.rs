@@ -0,0 +1,244 @@
+// src/dispatcher.rs
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce(&AtmiCtx) + Send>;
+
+/// Pool of worker threads, each owning its own joined `AtmiCtx`.
+///
+/// Jobs (`FnOnce(&AtmiCtx) -> R`) are queued over a channel and run by the
+/// first free worker, so any thread (or async task) may do XATMI calls
+/// without moving the context. Dropping the dispatcher lets the workers
+/// finish the queued jobs, then terminates their sessions.
+#[derive(Debug)]
+pub struct CtxDispatcher {
+    tx: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl CtxDispatcher {
+    /// Start `workers` threads, contexts joined with plain `tpinit`.
+    pub fn new(workers: usize) -> AtmiResult<Self> {
+        Self::with_init(workers, |ctx: &AtmiCtx| ctx.tpinit())
+    }
+
+    /// Start `workers` threads, each context joined with `init`.
+    /// Returns the first init failure, the started workers are stopped then.
+    pub fn with_init<F>(workers: usize, init: F) -> AtmiResult<Self>
+    where
+        F: Fn(&AtmiCtx) -> AtmiResult<()> + Send + Sync + 'static,
+    {
+        if workers == 0 {
+            return Err(AtmiError::new(raw::TPEINVAL, "number of workers must be positive"));
+        }
+
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let init = Arc::new(init);
+
+        let mut dispatcher = CtxDispatcher { tx: Some(tx), workers: Vec::with_capacity(workers) };
+
+        for n in 0..workers {
+            let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+            let rx = Arc::clone(&rx);
+            let init = Arc::clone(&init);
+
+            let worker = thread::Builder::new()
+                .name(format!("atmi-worker-{n}"))
+                .spawn(move || worker_main(&*init, &rx, ready_tx))
+                .map_err(|e| AtmiError::new(raw::TPEOS, format!("failed to spawn worker: {e}")))?;
+
+            dispatcher.workers.push(worker);
+
+            ready_rx.recv().unwrap_or_else(|_| {
+                Err(AtmiError::new(raw::TPESYSTEM, "worker exited during init"))
+            })?;
+        }
+
+        Ok(dispatcher)
+    }
+
+    /// Number of worker threads.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queue `job` for a worker. The result is received with the returned
+    /// handle, either blocking (`JobHandle::wait`) or awaited as a future.
+    pub fn submit<F, R>(&self, job: F) -> JobHandle<R>
+    where
+        F: FnOnce(&AtmiCtx) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let shared = Arc::new(JobShared { state: Mutex::new(JobState::default()), done: Condvar::new() });
+        let completer = Completer { shared: Some(Arc::clone(&shared)) };
+
+        let job: Job = Box::new(move |ctx: &AtmiCtx| {
+            let rc = panic::catch_unwind(AssertUnwindSafe(|| job(ctx)))
+                .map_err(|_| AtmiError::new(raw::TPESYSTEM, "dispatcher job panicked"));
+            completer.complete(rc);
+        });
+
+        // On send failure the job (and its completer) is dropped, which
+        // reports the error to the handle.
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(job);
+        }
+
+        JobHandle { shared }
+    }
+}
+
+impl Drop for CtxDispatcher {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_main(
+    init: &(dyn Fn(&AtmiCtx) -> AtmiResult<()> + Send + Sync),
+    rx: &Mutex<mpsc::Receiver<Job>>,
+    ready: mpsc::SyncSender<AtmiResult<()>>,
+) {
+    let ctx = match AtmiCtx::new().and_then(|ctx| init(&ctx).map(|()| ctx)) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let _ = ready.send(Ok(()));
+    drop(ready);
+
+    loop {
+        // Lock is held only while waiting for the next job
+        let job = rx.lock().unwrap_or_else(|e| e.into_inner()).recv();
+
+        match job {
+            Ok(job) => job(&ctx),
+            Err(_) => break,
+        }
+    }
+}
+
+struct JobState<R> {
+    result: Option<AtmiResult<R>>,
+    waker: Option<Waker>,
+}
+
+impl<R> Default for JobState<R> {
+    fn default() -> Self {
+        JobState { result: None, waker: None }
+    }
+}
+
+struct JobShared<R> {
+    state: Mutex<JobState<R>>,
+    done: Condvar,
+}
+
+impl<R> JobShared<R> {
+    fn lock(&self) -> MutexGuard<'_, JobState<R>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Delivers the job result; if the job is dropped unrun, delivers an error.
+struct Completer<R> {
+    shared: Option<Arc<JobShared<R>>>,
+}
+
+impl<R> Completer<R> {
+    fn complete(mut self, rc: AtmiResult<R>) {
+        if let Some(shared) = self.shared.take() {
+            Self::deliver(&shared, rc);
+        }
+    }
+
+    fn deliver(shared: &JobShared<R>, rc: AtmiResult<R>) {
+        let waker = {
+            let mut state = shared.lock();
+            state.result = Some(rc);
+            state.waker.take()
+        };
+
+        shared.done.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<R> Drop for Completer<R> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            Self::deliver(
+                &shared,
+                Err(AtmiError::new(raw::TPESYSTEM, "dispatcher stopped before the job ran")),
+            );
+        }
+    }
+}
+
+/// Result of the job queued with `CtxDispatcher::submit`.
+///
+/// `wait()` blocks the thread; the handle is also a `Future` for async code.
+/// Dropping the handle does not cancel the job.
+pub struct JobHandle<R> {
+    shared: Arc<JobShared<R>>,
+}
+
+impl<R> std::fmt::Debug for JobHandle<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobHandle").field("done", &self.is_done()).finish()
+    }
+}
+
+impl<R> JobHandle<R> {
+    /// Has the job finished?
+    pub fn is_done(&self) -> bool {
+        self.shared.lock().result.is_some()
+    }
+
+    /// Block until the job has finished and return its result.
+    /// `TPESYSTEM` if the job panicked or was never run.
+    pub fn wait(self) -> AtmiResult<R> {
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some(rc) = state.result.take() {
+                return rc;
+            }
+            state = self.shared.done.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl<R> Future for JobHandle<R> {
+    type Output = AtmiResult<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock();
+
+        match state.result.take() {
+            Some(rc) => Poll::Ready(rc),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// src/fan_out.rs
+use core::ffi::c_int;
+use crate::{raw, AtmiCtx, AtmiError, BlkTimeScope, CallDescriptor, CallError, CallFlags,
+    CallResult, TypedBuffer};
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Poll interval for the replies in the last second before the timeout.
+const LAST_POLL: Duration = Duration::from_millis(10);
+
+/// Results of `FanOut::run`, keyed by request.
+pub type FanOutResults<'ctx, K> = HashMap<K, CallResult<'ctx, TypedBuffer<'ctx>>>;
+
+struct FanOutRequest<'ctx, K> {
+    key: K,
+    service: String,
+    data: TypedBuffer<'ctx>,
+}
+
+struct Outstanding<'ctx, K> {
+    key: K,
+    call: CallDescriptor<'ctx>,
+}
+
+/// Scatter/gather of service calls, made by `AtmiCtx::fan_out`.
+///
+/// All requests are sent with *tpacall(3)*, then replies are collected with
+/// *tpgetrply(3)* (TPGETANY) until all arrived or the timeout passed. Calls
+/// still outstanding then are cancelled and reported with `TPETIME`.
+///
+/// As *tpsblktime(3)* takes whole seconds, the replies are waited for in
+/// whole seconds and polled every 10 ms in the last second, so the timeout
+/// is not overrun. If the context has other outstanding calls, all requests
+/// fail with `TPEPROTO` without being sent, as the replies of those calls
+/// would be consumed too. The same applies to the error of building the
+/// fan-out, e.g. `TPEINVAL` for a key added twice.
+pub struct FanOut<'ctx, K> {
+    ctx: &'ctx AtmiCtx,
+    requests: Vec<FanOutRequest<'ctx, K>>,
+    flags: CallFlags,
+    timeout: Option<Duration>,
+    /// First error of `call()`, reported by `run()`
+    error: Option<AtmiError>,
+}
+
+impl<K: std::fmt::Debug> std::fmt::Debug for FanOut<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FanOut")
+            .field("requests", &self.requests.iter().map(|r| (&r.key, &r.service)).collect::<Vec<_>>())
+            .field("flags", &self.flags)
+            .field("timeout", &self.timeout)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<'ctx, K: Eq + Hash> FanOut<'ctx, K> {
+    /// Add request `data` to `service`, its result is reported under `key`.
+    ///
+    /// Request with `key` already added is not taken, `run()` then fails all
+    /// requests with `TPEINVAL`.
+    pub fn call(mut self, key: K, service: impl Into<String>, data: TypedBuffer<'ctx>) -> Self {
+        if self.requests.iter().any(|r| r.key == key) {
+            self.error.get_or_insert_with(|| {
+                AtmiError::new(raw::TPEINVAL, "fan-out request key already added")
+            });
+            return self;
+        }
+
+        self.requests.push(FanOutRequest { key, service: service.into(), data });
+        self
+    }
+
+    /// Flags of all calls, `TPNOREPLY` is ignored.
+    pub fn flags(mut self, flags: CallFlags) -> Self {
+        self.flags = flags - CallFlags::TPNOREPLY;
+        self
+    }
+
+    /// Time to wait for all replies, counted from `run()`. The deadline of the
+    /// context (`AtmiCtx::set_deadline`) is not overrun.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send all requests and collect the replies.
+    pub fn run(self) -> FanOutResults<'ctx, K> {
+        let ctx = self.ctx;
+        let deadline = match (self.timeout.map(|t| Instant::now() + t), ctx.deadline()) {
+            (Some(own), Some(ctx_deadline)) => Some(own.min(ctx_deadline)),
+            (own, ctx_deadline) => own.or(ctx_deadline),
+        };
+
+        let mut results = HashMap::with_capacity(self.requests.len());
+        let mut pending: HashMap<c_int, Outstanding<'ctx, K>> = HashMap::new();
+
+        let error = self.error.or_else(|| {
+            (ctx.calls.outstanding() > 0)
+                .then(|| AtmiError::new(raw::TPEPROTO, "context has other outstanding calls"))
+        });
+
+        if let Some(error) = error {
+            for req in self.requests {
+                results.insert(req.key, Err(CallError::from(error.clone()).with_service(req.service)));
+            }
+            return results;
+        }
+
+        for mut req in self.requests {
+            match ctx.tpacall(&req.service, &mut req.data, self.flags) {
+                Ok(Some(call)) => {
+                    pending.insert(call.cd(), Outstanding { key: req.key, call });
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    results.insert(req.key, Err(CallError::from(e).with_service(req.service)));
+                }
+            }
+        }
+
+        // only reply related flags apply to tpgetrply
+        let rply_flags = self.flags & (CallFlags::TPSIGRSTRT | CallFlags::TPNOCHANGE);
+
+        // error which stopped the collection, reported for the remaining calls
+        let mut failure: Option<AtmiError> = None;
+
+        while !pending.is_empty() {
+            let mut flags = rply_flags;
+            let mut left = Duration::ZERO;
+
+            if let Some(deadline) = deadline {
+                left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    break;
+                }
+
+                // whole seconds left are waited, the rest polled
+                if left.as_secs() == 0 {
+                    flags |= CallFlags::TPNOBLOCK;
+                } else if ctx.tpsblktime(Duration::from_secs(left.as_secs()), BlkTimeScope::Next).is_err() {
+                    break;
+                }
+            }
+
+            match ctx.getrply_any(flags) {
+                Ok((cd, reply)) => {
+                    if let Some(o) = pending.remove(&cd) {
+                        results.insert(o.key, Ok(reply));
+                    }
+                }
+                Err(e) => match e.cd.and_then(|cd| pending.remove(&cd)) {
+                    Some(o) => {
+                        results.insert(o.key, Err(e));
+                    }
+                    None if e.code == AtmiError::TPEBLOCK && flags.contains(CallFlags::TPNOBLOCK) => {
+                        thread::sleep(LAST_POLL.min(left));
+                    }
+                    // deadline is checked on the next round
+                    None if e.code == AtmiError::TPGOTSIG
+                        || (e.code == AtmiError::TPETIME && deadline.is_some()) => {}
+                    None => {
+                        crate::tp_error!(ctx, "Fan-out reply collection failed: {}", e);
+                        failure = Some(e.error);
+                        break;
+                    }
+                },
+            }
+        }
+
+        for (cd, o) in pending {
+            let service = o.call.service();
+            let _ = o.call.cancel();
+
+            let err = failure.clone().unwrap_or_else(|| {
+                AtmiError::new(raw::TPETIME, "no reply until fan-out timeout, call cancelled")
+            });
+            results.insert(o.key, Err(CallError::from(err).with_service(service).with_cd(cd)));
+        }
+
+        results
+    }
+}
+
+impl AtmiCtx {
+    /// Start building parallel calls of several services, see `FanOut`.
+    pub fn fan_out<K: Eq + Hash>(&self) -> FanOut<'_, K> {
+        FanOut {
+            ctx: self,
+            requests: Vec::new(),
+            flags: CallFlags::empty(),
+            timeout: None,
+            error: None,
+        }
+    }
+}
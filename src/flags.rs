@@ -0,0 +1,47 @@
+// src/flags.rs
+use core::ffi::c_long;
+use bitflags::bitflags;
+
+use crate::raw;
+
+bitflags! {
+    /// Flags for the XATMI call family (tpcall, tpacall, tpgetrply, ...).
+    /// See *tpcall(3)* for the meaning of each flag.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct CallFlags: c_long {
+        /// Do not run the call as part of the current global transaction.
+        const TPNOTRAN      = raw::TPNOTRAN as c_long;
+        /// Fail with TPEBLOCK instead of waiting on blocking conditions.
+        const TPNOBLOCK     = raw::TPNOBLOCK as c_long;
+        /// Do not expect a reply (tpacall only).
+        const TPNOREPLY     = raw::TPNOREPLY as c_long;
+        /// Ignore blocking timeouts.
+        const TPNOTIME      = raw::TPNOTIME as c_long;
+        /// Restart system calls interrupted by signals.
+        const TPSIGRSTRT    = raw::TPSIGRSTRT as c_long;
+        /// Reply buffer must keep the type of the output buffer.
+        const TPNOCHANGE    = raw::TPNOCHANGE as c_long;
+        /// Conversation: give control to the other side (tpconnect, tpsend).
+        const TPSENDONLY    = raw::TPSENDONLY as c_long;
+        /// Conversation: keep control on the other side (tpconnect, tpsend).
+        const TPRECVONLY    = raw::TPRECVONLY as c_long;
+        /// Suspend the global transaction for the duration of the call.
+        const TPTRANSUSPEND = raw::TPTRANSUSPEND as c_long;
+    }
+}
+
+bitflags! {
+    /// Unsolicited notification flags of `TPINIT`.
+    /// See *tpinit(3)* for the meaning of each flag.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct TpInitFlags: c_long {
+        /// Notification by signal.
+        const TPU_SIG    = raw::TPU_SIG as c_long;
+        /// Notification by dip-in, i.e. checked during XATMI calls.
+        const TPU_DIP    = raw::TPU_DIP as c_long;
+        /// Ignore unsolicited messages.
+        const TPU_IGN    = raw::TPU_IGN as c_long;
+        /// Notification by separate thread.
+        const TPU_THREAD = raw::TPU_THREAD as c_long;
+    }
+}
@@ -3,17 +3,74 @@ pub(crate) mod raw { include!(concat!(env!("OUT_DIR"), "/bindings.rs")); }
 
 // your high-level modules
 mod atmictx;
+mod atmictx_call;
 mod atmictx_log;
+#[cfg(feature = "tokio")]
+mod async_call;
+mod blktime;
+mod call_descriptor;
+mod call_error;
+mod call_options;
+mod conversation;
+mod ctx_guard;
+#[cfg(feature = "ctx-send")]
+mod ctx_pool;
+mod deadline;
+#[cfg(feature = "server")]
+mod deferred;
+mod dispatcher;
 mod errors;
+mod fan_out;
+mod flags;
+#[cfg(feature = "server")]
+mod periodic;
+#[cfg(feature = "server")]
+mod poller;
+mod priority;
+mod retry;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+mod service_reply;
+mod tpinit;
 mod typed_buf;
 mod typed_ubf;
+#[cfg(feature = "server")]
 mod tpsvcinfo;
 
 // re-export the public façade so external users/tests can `use endurox_rs::AtmiCtx`
 pub use errors::{AtmiError, AtmiResult, UbfError, UbfResult, NstdError, NstdResult};
 pub use atmictx::AtmiCtx;
 pub use atmictx_log::LogLevel;
-pub use typed_buf::TypedBuffer;
-pub use typed_ubf::TypedUbf;
+#[cfg(feature = "tokio")]
+pub use async_call::AsyncCaller;
+pub use blktime::{BlkTimeGuard, BlkTimeScope};
+pub use call_descriptor::CallDescriptor;
+pub use call_error::{CallError, CallResult};
+pub use call_options::CallOptions;
+pub use conversation::{Conversation, ConvEvent};
+pub use ctx_guard::CtxGuard;
+pub use deadline::set_global_deadline_field;
+#[cfg(feature = "ctx-send")]
+pub use ctx_pool::{AtmiCtxPool, CtxLease, PoolCtxFn, PoolMetrics};
+#[cfg(feature = "server")]
+pub use deferred::DeferredRequest;
+pub use dispatcher::{CtxDispatcher, JobHandle};
+pub use fan_out::{FanOut, FanOutResults};
+pub use flags::{CallFlags, TpInitFlags};
+#[cfg(feature = "server")]
+pub use periodic::LoopCallbackFn;
+#[cfg(feature = "server")]
+pub use poller::{PollerFd, PollerFn};
+pub use priority::Priority;
+pub use retry::{CircuitBreaker, RetryPolicy};
+pub use typed_buf::{TypedBuffer, TypedBufferRef};
+pub use typed_ubf::{TypedUbf, TypedUbfRef};
 pub use typed_ubf::UbfValue;
+#[cfg(feature = "server")]
 pub use tpsvcinfo::TpSvcInfo;
+pub use tpinit::TpInitBuilder;
+#[cfg(feature = "server")]
+pub use server::{run_server, Server, ServiceFn, SyncServiceFn};
+#[cfg(feature = "server")]
+pub use service_reply::ServiceReply;
@@ -0,0 +1,159 @@
+// src/periodic.rs
+use core::ffi::c_int;
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+use crate::server::{run_callback, with_thread_ctx};
+
+use std::{
+    cell::RefCell,
+    thread::LocalKey,
+    time::Duration,
+};
+
+/// Server main loop callback (periodic or before-poll).
+/// `Err` (or panic) makes the server shut down.
+pub type LoopCallbackFn = dyn FnMut(&AtmiCtx) -> AtmiResult<()>;
+
+/// Callback slot. The handler is taken out while it runs, so that it may
+/// replace or clear itself; `gen` tells if it did.
+#[derive(Default)]
+struct CbSlot {
+    handler: Option<Box<LoopCallbackFn>>,
+    gen: u64,
+}
+
+thread_local! {
+    static PERIODIC_CB: RefCell<CbSlot> = RefCell::new(CbSlot::default());
+    static B4POLL_CB: RefCell<CbSlot> = RefCell::new(CbSlot::default());
+}
+
+fn slot_set(slot: &'static LocalKey<RefCell<CbSlot>>, handler: Option<Box<LoopCallbackFn>>) {
+    slot.with(|s| {
+        let mut s = s.borrow_mut();
+        s.handler = handler;
+        s.gen += 1;
+    });
+}
+
+/// Invoke the callback from the slot, returns C result code.
+fn slot_run(slot: &'static LocalKey<RefCell<CbSlot>>, what: &str) -> c_int {
+    let (handler, gen) = slot.with(|s| {
+        let mut s = s.borrow_mut();
+        (s.handler.take(), s.gen)
+    });
+
+    let Some(mut handler) = handler else {
+        return raw::EXSUCCEED as c_int;
+    };
+
+    let rc = with_thread_ctx(|ctx| run_callback(ctx, what, || handler(ctx)))
+        .unwrap_or(raw::EXFAIL as c_int);
+
+    slot.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.gen == gen {
+            s.handler = Some(handler);
+        }
+    });
+
+    rc
+}
+
+unsafe extern "C" fn periodic_cb() -> c_int {
+    slot_run(&PERIODIC_CB, "Periodic callback")
+}
+
+unsafe extern "C" fn b4poll_cb() -> c_int {
+    slot_run(&B4POLL_CB, "Before-poll callback")
+}
+
+/// Period in whole seconds, rounded up.
+fn period_secs(period: Duration) -> AtmiResult<c_int> {
+    let secs = period.as_secs() + u64::from(period.subsec_nanos() > 0);
+
+    match c_int::try_from(secs) {
+        Ok(secs) if secs > 0 => Ok(secs),
+        _ => Err(AtmiError::new(
+            raw::TPEINVAL,
+            format!("invalid callback period {period:?}"),
+        )),
+    }
+}
+
+impl AtmiCtx {
+    /// Run `handler` periodically from the server main loop (tpext_addperiodcb).
+    /// Replaces the previous periodic callback. The period is rounded up to
+    /// whole seconds. Shall be called from the server main thread, e.g. in
+    /// `Server::init`. See *tpext_addperiodcb(3)* for more details.
+    pub fn set_periodic_callback<F>(&self, period: Duration, handler: F) -> AtmiResult<()>
+    where
+        F: FnMut(&AtmiCtx) -> AtmiResult<()> + 'static,
+    {
+        let secs = period_secs(period)?;
+
+        slot_set(&PERIODIC_CB, Some(Box::new(handler)));
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpext_addperiodcb(secs, Some(periodic_cb)) };
+
+            if rc == raw::EXFAIL as c_int {
+                slot_set(&PERIODIC_CB, None);
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Remove the periodic callback (tpext_delperiodcb).
+    /// See *tpext_delperiodcb(3)* for more details.
+    pub fn clear_periodic_callback(&self) -> AtmiResult<()> {
+        slot_set(&PERIODIC_CB, None);
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpext_delperiodcb() };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Run `handler` before the server main loop waits for the next
+    /// request (tpext_addb4pollcb). Replaces the previous callback.
+    /// See *tpext_addb4pollcb(3)* for more details.
+    pub fn set_b4poll_callback<F>(&self, handler: F) -> AtmiResult<()>
+    where
+        F: FnMut(&AtmiCtx) -> AtmiResult<()> + 'static,
+    {
+        slot_set(&B4POLL_CB, Some(Box::new(handler)));
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpext_addb4pollcb(Some(b4poll_cb)) };
+
+            if rc == raw::EXFAIL as c_int {
+                slot_set(&B4POLL_CB, None);
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Remove the before-poll callback (tpext_delb4pollcb).
+    /// See *tpext_delb4pollcb(3)* for more details.
+    pub fn clear_b4poll_callback(&self) -> AtmiResult<()> {
+        slot_set(&B4POLL_CB, None);
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpext_delb4pollcb() };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
@@ -0,0 +1,119 @@
+// src/poller.rs
+use core::ffi::{c_int, c_void};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+use crate::server::{run_callback, with_thread_ctx};
+
+use std::{
+    collections::HashMap,
+    os::fd::{AsRawFd, RawFd},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+/// Poller callback, gets the event mask (`POLLIN`, ...) and the context of
+/// the server thread. `Err` (or panic) makes the server shut down.
+pub type PollerFn = dyn FnMut(u32, &AtmiCtx) -> AtmiResult<()> + Send;
+
+type PollerEntry = Arc<Mutex<Box<PollerFn>>>;
+
+/// Callbacks of the registered file descriptors.
+static POLLERS: OnceLock<Mutex<HashMap<RawFd, PollerEntry>>> = OnceLock::new();
+
+fn pollers() -> MutexGuard<'static, HashMap<RawFd, PollerEntry>> {
+    POLLERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Registration of the file descriptor in the XATMI server poller, made by
+/// `AtmiCtx::add_poller_fd`. Dropping it removes the registration
+/// (tpext_delpollerfd).
+///
+/// Does not borrow the context, so it may be kept in the `Server`, e.g. made
+/// in `Server::init` and dropped in `Server::done`.
+/// Drop the registration before closing the file descriptor.
+#[derive(Debug)]
+pub struct PollerFd {
+    fd: RawFd,
+}
+
+impl PollerFd {
+    /// Registered file descriptor.
+    #[inline]
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for PollerFd {
+    fn drop(&mut self) {
+        let del = || unsafe { raw::tpext_delpollerfd(self.fd) };
+
+        // Not on a server thread, the poller is process wide anyway
+        if with_thread_ctx(|ctx| ctx.with_ctx(del)).is_none() {
+            del();
+        }
+        pollers().remove(&self.fd);
+    }
+}
+
+/// C callback of all poller registrations, the callback is found by `fd`.
+unsafe extern "C" fn poll_event(fd: c_int, events: u32, _ptr1: *mut c_void) -> c_int {
+    // Entry is kept alive for the call, even if the registration is dropped
+    let Some(entry) = pollers().get(&fd).cloned() else {
+        return raw::EXSUCCEED as c_int;
+    };
+
+    with_thread_ctx(|ctx| {
+        run_callback(ctx, &format!("Poller callback for fd {fd}"), || {
+            let mut handler = entry.lock().unwrap_or_else(|e| e.into_inner());
+            handler(events, ctx)
+        })
+    })
+    .unwrap_or(raw::EXFAIL as c_int)
+}
+
+impl AtmiCtx {
+    /// Watch file descriptor in the XATMI server main loop (tpext_addpollerfd).
+    /// Shall be called from the server main thread, e.g. in `Server::init`.
+    /// The registration lasts until the returned `PollerFd` is dropped.
+    /// See *tpext_addpollerfd(3)* for more details.
+    ///
+    /// # Parameters
+    ///
+    /// * `fd` – socket, pipe, etc. to watch.
+    /// * `events` – poll event mask, e.g. `libc::POLLIN as u32`.
+    /// * `handler` – callback, invoked with the received events.
+    ///
+    /// # Errors
+    ///
+    /// * `TPEMATCH` – the file descriptor is already registered.
+    /// * other – as reported by *tpext_addpollerfd(3)*.
+    pub fn add_poller_fd<F>(&self, fd: &impl AsRawFd, events: u32, handler: F) -> AtmiResult<PollerFd>
+    where
+        F: FnMut(u32, &AtmiCtx) -> AtmiResult<()> + Send + 'static,
+    {
+        let fd = fd.as_raw_fd();
+
+        {
+            let mut pollers = pollers();
+            if pollers.contains_key(&fd) {
+                return Err(AtmiError::new(raw::TPEMATCH, format!("fd {fd} is already registered")));
+            }
+            pollers.insert(fd, Arc::new(Mutex::new(Box::new(handler))));
+        }
+
+        self.with_ctx(|| {
+            let rc = unsafe {
+                raw::tpext_addpollerfd(fd, events, std::ptr::null_mut(), Some(poll_event))
+            };
+
+            if rc == raw::EXFAIL as c_int {
+                pollers().remove(&fd);
+                Err(self.atmi_last_error())
+            } else {
+                Ok(PollerFd { fd })
+            }
+        })
+    }
+}
@@ -0,0 +1,49 @@
+// src/priority.rs
+use core::ffi::{c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiResult};
+
+/// Priority of the next service call, see `AtmiCtx::tpsprio`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// Absolute priority, 1 (lowest) to 100 (highest) (TPABSOLUTE).
+    Absolute(i32),
+    /// Change relative to the default priority of the service.
+    Relative(i32),
+}
+
+impl AtmiCtx {
+    /// Set priority of the next service call (tpsprio).
+    /// See *tpsprio(3)* for more details.
+    pub fn tpsprio(&self, prio: Priority) -> AtmiResult<()> {
+        self.ensure_joined()?;
+        let (prio, flags) = match prio {
+            Priority::Absolute(p) => (p, raw::TPABSOLUTE as c_long),
+            Priority::Relative(p) => (p, 0),
+        };
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpsprio(prio as c_int, flags) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Priority of the last request sent or received (tpgprio).
+    /// In a service, `TpSvcInfo::priority` gives the priority of the request.
+    /// See *tpgprio(3)* for more details.
+    pub fn tpgprio(&self) -> AtmiResult<i32> {
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpgprio() };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(rc)
+            }
+        })
+    }
+}
@@ -0,0 +1,219 @@
+// src/retry.rs
+use core::ffi::c_int;
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult};
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Retry of failed `tpcall_opts` calls, set in `CallOptions::retry`.
+///
+/// Only errors with retryable codes are retried, with exponential backoff
+/// between the attempts. Calls made in a global transaction (without
+/// `TPNOTRAN`) are never retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    /// Bit per retryable `AtmiError` code
+    retryable: u64,
+}
+
+impl Default for RetryPolicy {
+    /// 3 attempts, backoff from 100ms up to 2s, retry on TPENOENT, TPETIME
+    /// and TPELIMIT.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            retryable: 0,
+        }
+        .retry_on(&[AtmiError::TPENOENT, AtmiError::TPETIME, AtmiError::TPELIMIT])
+    }
+}
+
+impl RetryPolicy {
+    /// Default policy, see `Default`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total number of attempts, including the first call (at least 1).
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait `initial` after the first failure, doubled for each next one,
+    /// up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Replace the retryable error codes, e.g. `&[AtmiError::TPETIME]`.
+    pub fn retry_on(mut self, codes: &[u32]) -> Self {
+        self.retryable = codes
+            .iter()
+            .filter(|&&code| code < u64::BITS)
+            .fold(0, |bits, &code| bits | 1 << code);
+        self
+    }
+
+    /// Is the error code retryable?
+    pub fn is_retryable(&self, code: u32) -> bool {
+        code < u64::BITS && self.retryable & (1 << code) != 0
+    }
+
+    /// Number of attempts.
+    pub fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Wait before attempt `attempt` + 1 (attempts counted from 1).
+    pub(crate) fn backoff_for(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// Half-open trial call is in progress
+    probing: bool,
+}
+
+impl BreakerState {
+    /// Are the calls rejected at `now`?
+    fn is_open(&self, now: Instant) -> bool {
+        self.probing || self.open_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Per-service circuit breaker of `tpcall_opts`, see `AtmiCtx::set_circuit_breaker`.
+///
+/// After `failure_threshold` consecutive failed calls of a service (no
+/// service, timeouts, limits, service and system errors) calls to it fail
+/// fast with `TPELIMIT` for `open_for`. Then a single trial call is let
+/// through, the others still fail fast until it completes: success closes
+/// the circuit, failure opens it again. A `tpcall_opts` call counts once,
+/// with the result of its last retry attempt. May be shared by several
+/// contexts.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    services: Mutex<HashMap<String, BreakerState>>,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit after `failure_threshold` failures for `open_for`.
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            open_for,
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Is the circuit of `service` open (calls rejected) now?
+    pub fn is_open(&self, service: &str) -> bool {
+        let services = self.services.lock().unwrap_or_else(|e| e.into_inner());
+        services.get(service).is_some_and(|s| s.is_open(Instant::now()))
+    }
+
+    /// Fail with TPELIMIT while the circuit of `service` is open. Once
+    /// `open_for` passed, the first caller gets through as the trial call,
+    /// its result shall be given to `record`.
+    pub(crate) fn check(&self, service: &str) -> AtmiResult<()> {
+        let mut services = self.services.lock().unwrap_or_else(|e| e.into_inner());
+
+        let Some(state) = services.get_mut(service) else {
+            return Ok(());
+        };
+
+        if state.is_open(Instant::now()) {
+            return Err(AtmiError::new(
+                raw::TPELIMIT,
+                format!("circuit breaker open for service [{service}]"),
+            ));
+        }
+
+        if state.open_until.is_some() {
+            state.probing = true;
+        }
+
+        Ok(())
+    }
+
+    /// Record result of the call to `service`.
+    pub(crate) fn record(&self, service: &str, error: Option<&AtmiError>) {
+        let mut services = self.services.lock().unwrap_or_else(|e| e.into_inner());
+
+        match error {
+            Some(e) if is_fault(e.code) => {
+                let state = services.entry(service.to_owned()).or_default();
+                state.failures = state.failures.saturating_add(1);
+
+                // half-open trial failed, or threshold reached
+                if state.open_until.is_some() || state.failures >= self.failure_threshold {
+                    state.open_until = Some(Instant::now() + self.open_for);
+                }
+                state.probing = false;
+            }
+            // business failures (TPESVCFAIL) and caller errors do not count,
+            // the next caller makes the trial
+            Some(_) => {
+                if let Some(state) = services.get_mut(service) {
+                    state.probing = false;
+                }
+            }
+            None => {
+                services.remove(service);
+            }
+        }
+    }
+}
+
+/// Errors which tell that the service is unavailable or unhealthy.
+fn is_fault(code: u32) -> bool {
+    matches!(
+        code,
+        AtmiError::TPENOENT
+            | AtmiError::TPETIME
+            | AtmiError::TPELIMIT
+            | AtmiError::TPESVCERR
+            | AtmiError::TPESYSTEM
+            | AtmiError::TPEOS
+    )
+}
+
+impl AtmiCtx {
+    /// Is the context in a global transaction (tpgetlev)?
+    /// See *tpgetlev(3)* for more details.
+    pub fn tpgetlev(&self) -> AtmiResult<bool> {
+        self.ensure_joined()?;
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpgetlev() };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(rc > 0)
+            }
+        })
+    }
+
+    /// Use circuit breaker for the calls made with `tpcall_opts`, `None` to
+    /// disable. Share the `Arc` to have common circuits for several contexts.
+    pub fn set_circuit_breaker(&mut self, breaker: Option<Arc<CircuitBreaker>>) {
+        self.breaker = breaker;
+    }
+}
@@ -0,0 +1,612 @@
+// src/server.rs
+use core::ffi::{c_char, c_int, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, ServiceReply, TpSvcInfo, TypedBuffer};
+use crate::atmictx_call::service_cstr;
+
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::{Cell, OnceCell, RefCell},
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::unix::ffi::OsStringExt,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, Once, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+/// XATMI server implemented in Rust. See `run_server`.
+///
+/// With `MINDISPATCHTHREADS`/`MAXDISPATCHTHREADS` set in `<server>` config,
+/// services are dispatched by several threads. Each dispatch thread gets its
+/// own `AtmiCtx`, which never leaves that thread. The thread hooks run
+/// concurrently, the implementation synchronizes its own state.
+pub trait Server: Send + Sync {
+    /// Server startup (tpsvrinit). Services are advertised here
+    /// with `AtmiCtx::advertise`. Error or panic aborts the server boot.
+    ///
+    /// # Parameters
+    ///
+    /// * `ctx` – ATMI context of the server main thread.
+    /// * `args` – server command line arguments (`-- ...` part of `<clopt>`).
+    fn init(&mut self, ctx: &AtmiCtx, args: &[String]) -> AtmiResult<()>;
+
+    /// Server shutdown (tpsvrdone). Panic is logged.
+    fn done(&mut self, _ctx: &AtmiCtx) {}
+
+    /// Dispatch thread startup (tpsvrthrinit), multi-threaded servers only.
+    /// Error or panic aborts the server boot.
+    ///
+    /// # Parameters
+    ///
+    /// * `ctx` – ATMI context of the dispatch thread.
+    /// * `args` – server command line arguments.
+    fn thread_init(&self, _ctx: &AtmiCtx, _args: &[String]) -> AtmiResult<()> {
+        Ok(())
+    }
+
+    /// Dispatch thread shutdown (tpsvrthrdone), multi-threaded servers only.
+    /// Panic is logged.
+    fn thread_done(&self, _ctx: &AtmiCtx) {}
+
+    /// User return code (tpurcode) of TPFAIL reply sent when a service
+    /// handler panics.
+    fn panic_rcode(&self) -> i64 {
+        0
+    }
+
+    /// Number of service handler panics after which the server exits
+    /// (tpexit), so that ndrxd restarts it. `None` – never.
+    fn max_panics(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Service handler, gets the request and returns how to complete it.
+/// `Err` is logged and returned to the caller as TPFAIL.
+///
+/// In multi-threaded servers calls are serialized, one request at a time.
+pub type ServiceFn = dyn for<'a> FnMut(&'a AtmiCtx, TpSvcInfo<'a>) -> AtmiResult<ServiceReply<'a>>
+    + Send;
+
+/// Service handler which is safe to run concurrently by several dispatch
+/// threads of the multi-threaded server. See `ServiceFn`.
+pub type SyncServiceFn = dyn for<'a> Fn(&'a AtmiCtx, TpSvcInfo<'a>) -> AtmiResult<ServiceReply<'a>>
+    + Send
+    + Sync;
+
+enum Handler {
+    /// `advertise` – one request at a time
+    Exclusive(Mutex<Box<ServiceFn>>),
+    /// `advertise_sync` – concurrent requests
+    Shared(Box<SyncServiceFn>),
+}
+
+type ServiceEntry = Arc<Handler>;
+
+/// Written by init/done and `run_server`, read by the thread hooks.
+static SERVER: RwLock<Option<Box<dyn Server>>> = RwLock::new(None);
+static SERVICES: OnceLock<Mutex<HashMap<String, ServiceEntry>>> = OnceLock::new();
+
+static PANIC_RCODE: AtomicI64 = AtomicI64::new(0);
+static MAX_PANICS: AtomicU32 = AtomicU32::new(0);
+static PANIC_COUNT: AtomicU32 = AtomicU32::new(0);
+static PANIC_HOOK: Once = Once::new();
+static REQUEST_SEQ: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Context of the dispatching thread, handed to services.
+    static SERVER_CTX: OnceCell<AtmiCtx> = const { OnceCell::new() };
+
+    /// Id of the request whose handler runs on this thread, 0 if none.
+    static IN_HANDLER: Cell<u64> = const { Cell::new(0) };
+
+    /// Rust code run by `catch_panic` is running on this thread.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+
+    /// Running handler deferred the reply (`TpSvcInfo::defer`).
+    static DEFERRED: Cell<bool> = const { Cell::new(false) };
+
+    /// Message and backtrace of the last caught panic.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Lock ignoring poison, as handler panics must not take the server down.
+fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn server() -> RwLockReadGuard<'static, Option<Box<dyn Server>>> {
+    SERVER.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn server_mut() -> RwLockWriteGuard<'static, Option<Box<dyn Server>>> {
+    SERVER.write().unwrap_or_else(|e| e.into_inner())
+}
+
+fn services() -> MutexGuard<'static, HashMap<String, ServiceEntry>> {
+    lock(SERVICES.get_or_init(Default::default))
+}
+
+/// Text of the panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Capture panics of service handlers and server hooks for logging; the
+/// backtrace is only available inside the hook. Other panics go to the
+/// previous hook.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let prev = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                prev(info);
+                return;
+            }
+
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}", l.file(), l.line()))
+                .unwrap_or_default();
+
+            let text = format!(
+                "{} at {}\n{}",
+                panic_message(info.payload()),
+                location,
+                Backtrace::force_capture()
+            );
+
+            LAST_PANIC.with(|p| *p.borrow_mut() = Some(text));
+        }));
+    });
+}
+
+/// Run `f`, a panic must not unwind into XATMI. Its message with the
+/// backtrace captured by the panic hook is returned as error.
+fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    let prev = CATCHING.with(|c| c.replace(true));
+    let rc = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(prev));
+
+    rc.map_err(|payload| {
+        LAST_PANIC
+            .with(|p| p.borrow_mut().take())
+            .unwrap_or_else(|| panic_message(payload.as_ref()).to_string())
+    })
+}
+
+/// Service handler panicked: log it, count it and fail the request.
+fn handler_panicked(ctx: &AtmiCtx, name: &str, text: &str) -> Completion {
+    crate::tp_error!(ctx, "Service [{}] panicked: {}", name, text);
+
+    let count = PANIC_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let max = MAX_PANICS.load(Ordering::Relaxed);
+
+    if max > 0 && count >= max {
+        crate::tp_error!(ctx, "{} service panics, requesting server restart", count);
+        unsafe { raw::tpexit() };
+    }
+
+    Completion::Return {
+        rval: raw::TPFAIL as c_int,
+        rcode: PANIC_RCODE.load(Ordering::Relaxed) as c_long,
+        data: ptr::null_mut(),
+    }
+}
+
+/// Run server hook `f` (init, done...), a panic is logged and `None` returned.
+fn catch_hook<R>(ctx: &AtmiCtx, what: &str, f: impl FnOnce() -> R) -> Option<R> {
+    catch_panic(f)
+        .map_err(|text| {
+            crate::tp_error!(ctx, "{} panicked: {}", what, text);
+        })
+        .ok()
+}
+
+/// Run main loop callback `f` (poller, periodic...) of the server, returns
+/// the C result code. Error or panic is logged, `EXFAIL` shuts the server down.
+pub(crate) fn run_callback(ctx: &AtmiCtx, what: &str, f: impl FnOnce() -> AtmiResult<()>) -> c_int {
+    match catch_hook(ctx, what, f) {
+        Some(Ok(())) => raw::EXSUCCEED as c_int,
+        Some(Err(e)) => {
+            crate::tp_error!(ctx, "{} failed, shutting down: {}", what, e);
+            raw::EXFAIL as c_int
+        }
+        None => raw::EXFAIL as c_int,
+    }
+}
+
+/// Convert C argument vector to Rust strings.
+unsafe fn args_from_raw(argc: c_int, argv: *mut *mut c_char) -> Vec<String> {
+    (0..argc as isize)
+        .map(|i| *argv.offset(i))
+        .filter(|a| !a.is_null())
+        .map(|a| CStr::from_ptr(a).to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Run `f` with the context of the current server thread,
+/// attached on first use. `None` if XATMI context is not available.
+pub(crate) fn with_thread_ctx<R>(f: impl FnOnce(&AtmiCtx) -> R) -> Option<R> {
+    SERVER_CTX.with(|cell| {
+        if cell.get().is_none() {
+            let _ = cell.set(AtmiCtx::attach_current().ok()?);
+        }
+        cell.get().map(f)
+    })
+}
+
+fn not_registered() -> AtmiError {
+    AtmiError::new(raw::TPESYSTEM, "server not registered")
+}
+
+/// Common part of tpsvrinit and tpsvrthrinit hooks.
+unsafe fn run_init(
+    argc: c_int,
+    argv: *mut *mut c_char,
+    what: &str,
+    init: impl FnOnce(&AtmiCtx, &[String]) -> AtmiResult<()>,
+) -> c_int {
+    let args = args_from_raw(argc, argv);
+
+    with_thread_ctx(|ctx| {
+        let rc = catch_hook(ctx, what, || init(ctx, &args))
+            .unwrap_or_else(|| Err(AtmiError::new(raw::TPESYSTEM, format!("{what} panicked"))));
+
+        match rc {
+            Ok(()) => raw::EXSUCCEED as c_int,
+            Err(e) => {
+                crate::tp_error!(ctx, "{} failed: {}", what, e);
+                raw::EXFAIL as c_int
+            }
+        }
+    })
+    .unwrap_or(raw::EXFAIL as c_int)
+}
+
+/// tpsvrinit hook
+unsafe extern "C" fn svrinit(argc: c_int, argv: *mut *mut c_char) -> c_int {
+    run_init(argc, argv, "Server init", |ctx, args| match server_mut().as_mut() {
+        Some(server) => server.init(ctx, args),
+        None => Err(not_registered()),
+    })
+}
+
+/// tpsvrdone hook
+unsafe extern "C" fn svrdone() {
+    with_thread_ctx(|ctx| {
+        catch_hook(ctx, "Server done", || {
+            if let Some(server) = server_mut().as_mut() {
+                server.done(ctx);
+            }
+        });
+    });
+}
+
+/// tpsvrthrinit hook
+unsafe extern "C" fn svrthrinit(argc: c_int, argv: *mut *mut c_char) -> c_int {
+    // Shared lock, dispatch threads start concurrently
+    run_init(argc, argv, "Server thread init", |ctx, args| match server().as_ref() {
+        Some(server) => server.thread_init(ctx, args),
+        None => Err(not_registered()),
+    })
+}
+
+/// tpsvrthrdone hook
+unsafe extern "C" fn svrthrdone() {
+    with_thread_ctx(|ctx| {
+        catch_hook(ctx, "Server thread done", || {
+            if let Some(server) = server().as_ref() {
+                server.thread_done(ctx);
+            }
+        });
+    });
+}
+
+const SVCNM_BUF: usize = raw::XATMI_SERVICE_NAME_LENGTH as usize + 1;
+
+/// Arguments of tpreturn()/tpforward()/tpcontinue(). Plain data only, nothing to drop.
+#[derive(Clone, Copy)]
+pub(crate) enum Completion {
+    Return { rval: c_int, rcode: c_long, data: *mut c_char },
+    Forward { service: [c_char; SVCNM_BUF], data: *mut c_char },
+    Continue,
+}
+
+impl Completion {
+    pub(crate) fn fail() -> Self {
+        Completion::Return { rval: raw::TPFAIL as c_int, rcode: 0, data: ptr::null_mut() }
+    }
+
+    /// Reply is not sent, free its data.
+    fn discard(self) {
+        if let Completion::Return { data, .. } | Completion::Forward { data, .. } = self {
+            if !data.is_null() {
+                unsafe { raw::tpfree(data) };
+            }
+        }
+    }
+}
+
+/// Complete the request. On the dispatching thread call it with no Rust
+/// frames left to unwind, as tpreturn(), tpforward() and tpcontinue() may
+/// not return there. Threads which restored the server context data
+/// (tpsrvsetctxdata) return normally.
+pub(crate) unsafe fn finish(completion: Completion) {
+    match completion {
+        Completion::Return { rval, rcode, data } => raw::tpreturn(rval, rcode, data, 0, 0),
+        Completion::Forward { mut service, data } => {
+            raw::tpforward(service.as_mut_ptr(), data, 0, 0)
+        }
+        Completion::Continue => raw::tpcontinue(),
+    }
+}
+
+/// Mark the running request as deferred, returns its id. `None` if not
+/// called from the service handler, or the request is already deferred.
+pub(crate) fn begin_defer() -> Option<u64> {
+    handled_request().filter(|_| !DEFERRED.with(|f| f.replace(true)))
+}
+
+/// Id of the request whose handler runs on this thread.
+pub(crate) fn handled_request() -> Option<u64> {
+    Some(IN_HANDLER.with(Cell::get)).filter(|&id| id != 0)
+}
+
+/// Undo `begin_defer`, when server context data cannot be captured.
+pub(crate) fn cancel_defer() {
+    DEFERRED.with(|f| f.set(false));
+}
+
+/// Pass reply data ownership to XATMI.
+fn release(data: Option<TypedBuffer<'_>>) -> *mut c_char {
+    data.map_or(ptr::null_mut(), TypedBuffer::into_raw)
+}
+
+/// Turn handler outcome to the tpreturn()/tpforward()/tpcontinue() arguments.
+pub(crate) fn completion(ctx: &AtmiCtx, name: &str, reply: AtmiResult<ServiceReply<'_>>) -> Completion {
+    match reply {
+        Ok(ServiceReply::Success { rcode, data }) => Completion::Return {
+            rval: raw::TPSUCCESS as c_int,
+            rcode: rcode as c_long,
+            data: release(data),
+        },
+        Ok(ServiceReply::Fail { rcode, data }) => Completion::Return {
+            rval: raw::TPFAIL as c_int,
+            rcode: rcode as c_long,
+            data: release(data),
+        },
+        Ok(ServiceReply::Forward { service, data }) => match service_name_cstr(&service) {
+            Ok(service_c) => {
+                let mut buf = [0 as c_char; SVCNM_BUF];
+                for (dst, src) in buf.iter_mut().zip(service_c.as_bytes()) {
+                    *dst = *src as c_char;
+                }
+                Completion::Forward { service: buf, data: release(data) }
+            }
+            Err(e) => {
+                crate::tp_error!(ctx, "Service [{}] cannot forward: {}", name, e);
+                Completion::fail()
+            }
+        },
+        Ok(ServiceReply::Deferred) => Completion::Continue,
+        Err(e) => {
+            crate::tp_error!(ctx, "Service [{}] failed: {}", name, e);
+            Completion::fail()
+        }
+    }
+}
+
+/// Run the service handler, returns how the request shall complete.
+fn dispatch_request(svcinfo: *mut raw::TPSVCINFO) -> Completion {
+    with_thread_ctx(|ctx| {
+        let info = unsafe { TpSvcInfo::from_raw(ctx, svcinfo) };
+        let name = info.name().to_string();
+
+        // Aliases (e.g. `-s ALIAS:SVC` in <clopt>) arrive with the function
+        // name of the original service. Do not hold the table lock during
+        // the call, handler may (un)advertise.
+        let entry = {
+            let table = services();
+            table.get(&name).or_else(|| table.get(info.fname())).cloned()
+        };
+
+        let Some(entry) = entry else {
+            crate::tp_error!(ctx, "No handler for service [{}]", name);
+            return Completion::fail();
+        };
+
+        // Calls made by the handler are limited by the caller's deadline
+        ctx.set_deadline(info.deadline());
+
+        // Unwinding into the XATMI dispatcher would abort the process.
+        DEFERRED.with(|f| f.set(false));
+        IN_HANDLER.with(|f| f.set(REQUEST_SEQ.fetch_add(1, Ordering::Relaxed) + 1));
+        let reply = catch_panic(|| {
+            let reply = match entry.as_ref() {
+                Handler::Exclusive(handler) => lock(handler)(ctx, info),
+                Handler::Shared(handler) => handler(ctx, info),
+            };
+            completion(ctx, &name, reply)
+        });
+        IN_HANDLER.with(|f| f.set(0));
+        ctx.set_deadline(None);
+
+        let reply = reply.unwrap_or_else(|text| handler_panicked(ctx, &name, &text));
+
+        // Deferred request is completed by the DeferredRequest holder.
+        match (DEFERRED.with(|f| f.replace(false)), reply) {
+            (true, Completion::Continue) => Completion::Continue,
+            (true, reply) => {
+                crate::tp_error!(ctx, "Service [{}] deferred the request, reply dropped", name);
+                reply.discard();
+                Completion::Continue
+            }
+            (false, Completion::Continue) => {
+                crate::tp_error!(ctx, "Service [{}] returned Deferred without defer()", name);
+                Completion::fail()
+            }
+            (false, reply) => reply,
+        }
+    })
+    .unwrap_or_else(Completion::fail)
+}
+
+/// Single C entry point of all services advertised from Rust.
+unsafe extern "C" fn dispatch(svcinfo: *mut raw::TPSVCINFO) {
+    // All Rust frames of the request are unwound here.
+    finish(dispatch_request(svcinfo));
+}
+
+/// Validate service name before passing it to XATMI.
+fn service_name_cstr(name: &str) -> AtmiResult<CString> {
+    if name.is_empty() || name.len() > raw::XATMI_SERVICE_NAME_LENGTH as usize {
+        return Err(AtmiError::new(
+            raw::TPEINVAL,
+            format!(
+                "service name [{}] shall be 1..{} bytes long",
+                name,
+                raw::XATMI_SERVICE_NAME_LENGTH
+            ),
+        ));
+    }
+
+    service_cstr(name)
+}
+
+impl AtmiCtx {
+    /// tpadvertise the Rust dispatcher and register the handler.
+    fn advertise_handler(&self, name: &str, handler: Handler) -> AtmiResult<()> {
+        let name_c = service_name_cstr(name)?;
+
+        self.with_ctx(|| {
+            let rc = unsafe {
+                raw::tpadvertise_full(
+                    name_c.as_ptr() as *mut c_char,
+                    Some(dispatch),
+                    name_c.as_ptr() as *mut c_char,
+                )
+            };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })?;
+
+        services().insert(name.to_string(), Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Advertise service handled by the Rust closure (tpadvertise).
+    ///
+    /// All Rust services share single C entry point, requests are routed
+    /// by the service name, or by the function name for the aliases.
+    /// Advertising already advertised service replaces its handler.
+    /// In multi-threaded servers the handler runs for one request at a time,
+    /// see `advertise_sync` for concurrent handlers.
+    /// See *tpadvertise(3)* for more details.
+    ///
+    /// # Errors
+    ///
+    /// * `TPEINVAL` – name is empty or longer than `XATMI_SERVICE_NAME_LENGTH`.
+    /// * other – as reported by *tpadvertise(3)*.
+    pub fn advertise<F>(&self, name: &str, handler: F) -> AtmiResult<()>
+    where
+        F: for<'a> FnMut(&'a AtmiCtx, TpSvcInfo<'a>) -> AtmiResult<ServiceReply<'a>>
+            + Send
+            + 'static,
+    {
+        self.advertise_handler(name, Handler::Exclusive(Mutex::new(Box::new(handler))))
+    }
+
+    /// Advertise service handled by the `Sync` Rust closure (tpadvertise).
+    /// Dispatch threads of the multi-threaded server run it concurrently.
+    /// See `advertise` for details.
+    pub fn advertise_sync<F>(&self, name: &str, handler: F) -> AtmiResult<()>
+    where
+        F: for<'a> Fn(&'a AtmiCtx, TpSvcInfo<'a>) -> AtmiResult<ServiceReply<'a>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.advertise_handler(name, Handler::Shared(Box::new(handler)))
+    }
+
+    /// Unadvertise service (tpunadvertise) and release its handler.
+    /// Request being processed by the handler completes normally.
+    /// See *tpunadvertise(3)* for more details.
+    pub fn unadvertise(&self, name: &str) -> AtmiResult<()> {
+        let name_c = service_name_cstr(name)?;
+
+        self.with_ctx(|| {
+            let rc = unsafe { raw::tpunadvertise(name_c.as_ptr() as *mut c_char) };
+
+            if rc == raw::EXFAIL as c_int {
+                Err(self.atmi_last_error())
+            } else {
+                Ok(())
+            }
+        })?;
+
+        services().remove(name);
+
+        Ok(())
+    }
+}
+
+/// Start XATMI server (_tmstartserver, i.e. ndrx_main_integra with thread
+/// hooks) with the command line of the current process. Returns when the
+/// server is shut down.
+///
+/// # Returns
+///
+/// Process exit code, `0` on success.
+pub fn run_server<S: Server + 'static>(server: S) -> i32 {
+    PANIC_RCODE.store(server.panic_rcode(), Ordering::Relaxed);
+    MAX_PANICS.store(server.max_panics().unwrap_or(0), Ordering::Relaxed);
+    PANIC_COUNT.store(0, Ordering::Relaxed);
+    install_panic_hook();
+
+    *server_mut() = Some(Box::new(server));
+
+    let args: Vec<CString> = std::env::args_os()
+        .map(|a| CString::new(a.into_vec()).unwrap_or_default())
+        .collect();
+
+    let mut argv: Vec<*mut c_char> = args.iter().map(|a| a.as_ptr() as *mut c_char).collect();
+    argv.push(ptr::null_mut());
+
+    // ndrx_main_integra() has no tpsvrthrinit/tpsvrthrdone, thus the
+    // server is started via tmsvrargs. Empty service table, as services
+    // are advertised dynamically.
+    let mut svctab: [raw::tmdsptchtbl_t; 1] = unsafe { std::mem::zeroed() };
+    let mut svrargs: raw::tmsvrargs_t = unsafe { std::mem::zeroed() };
+    svrargs.svctab = svctab.as_mut_ptr();
+    svrargs.p_tpsvrinit = Some(svrinit);
+    svrargs.p_tpsvrdone = Some(svrdone);
+    svrargs.p_tpsvrthrinit = Some(svrthrinit);
+    svrargs.p_tpsvrthrdone = Some(svrthrdone);
+
+    let rc = unsafe {
+        raw::_tmstartserver(args.len() as c_int, argv.as_mut_ptr(), &mut svrargs)
+    };
+
+    *server_mut() = None;
+    services().clear();
+
+    rc
+}
@@ -0,0 +1,47 @@
+// src/service_reply.rs
+use crate::TypedBuffer;
+
+/// Outcome of the Rust service handler.
+///
+/// The server dispatcher turns it into *tpreturn(3)* or *tpforward(3)* after
+/// the handler has returned, so that no Rust destructors are skipped.
+/// The reply buffer ownership passes to XATMI.
+#[derive(Debug)]
+pub enum ServiceReply<'ctx> {
+    /// Reply with TPSUCCESS.
+    Success {
+        rcode: i64,
+        data: Option<TypedBuffer<'ctx>>,
+    },
+    /// Reply with TPFAIL, caller gets TPESVCFAIL.
+    Fail {
+        rcode: i64,
+        data: Option<TypedBuffer<'ctx>>,
+    },
+    /// Forward the request to another service.
+    Forward {
+        service: String,
+        data: Option<TypedBuffer<'ctx>>,
+    },
+    /// Request was handed over with `TpSvcInfo::defer`; the dispatcher
+    /// continues with *tpcontinue(3)* and the reply is sent by the
+    /// `DeferredRequest` holder.
+    Deferred,
+}
+
+impl<'ctx> ServiceReply<'ctx> {
+    /// TPSUCCESS with `rcode` 0.
+    pub fn success(data: Option<TypedBuffer<'ctx>>) -> Self {
+        ServiceReply::Success { rcode: 0, data }
+    }
+
+    /// TPFAIL with `rcode` 0.
+    pub fn fail(data: Option<TypedBuffer<'ctx>>) -> Self {
+        ServiceReply::Fail { rcode: 0, data }
+    }
+
+    /// Forward request to `service`.
+    pub fn forward(service: impl Into<String>, data: Option<TypedBuffer<'ctx>>) -> Self {
+        ServiceReply::Forward { service: service.into(), data }
+    }
+}
@@ -0,0 +1,152 @@
+// src/tpinit.rs
+use core::ffi::{c_char, c_long};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, TpInitFlags};
+
+use std::mem::{offset_of, size_of};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Builder of the `TPINIT` buffer for *tpinit(3)*, made by `AtmiCtx::tpinit_builder`.
+///
+/// Identification fields are limited to `MAXTIDENT` bytes. The password is
+/// kept in memory which is zeroed on drop, also in the `TPINIT` buffer.
+pub struct TpInitBuilder<'ctx> {
+    ctx: &'ctx AtmiCtx,
+    usrname: String,
+    cltname: String,
+    passwd: Zeroizing<String>,
+    grpname: String,
+    flags: TpInitFlags,
+    data: Vec<u8>,
+}
+
+impl std::fmt::Debug for TpInitBuilder<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TpInitBuilder")
+            .field("usrname", &self.usrname)
+            .field("cltname", &self.cltname)
+            .field("passwd", &"***")
+            .field("grpname", &self.grpname)
+            .field("flags", &self.flags)
+            .field("datalen", &self.data.len())
+            .finish()
+    }
+}
+
+impl<'ctx> TpInitBuilder<'ctx> {
+    fn new(ctx: &'ctx AtmiCtx) -> Self {
+        TpInitBuilder {
+            ctx,
+            usrname: String::new(),
+            cltname: String::new(),
+            passwd: Zeroizing::new(String::new()),
+            grpname: String::new(),
+            flags: TpInitFlags::empty(),
+            data: Vec::new(),
+        }
+    }
+
+    /// User name (`usrname`).
+    pub fn usrname(mut self, usrname: impl Into<String>) -> Self {
+        self.usrname = usrname.into();
+        self
+    }
+
+    /// Client name (`cltname`).
+    pub fn cltname(mut self, cltname: impl Into<String>) -> Self {
+        self.cltname = cltname.into();
+        self
+    }
+
+    /// Application password (`passwd`).
+    pub fn passwd(mut self, passwd: impl Into<String>) -> Self {
+        self.passwd = Zeroizing::new(passwd.into());
+        self
+    }
+
+    /// Group name (`grpname`).
+    pub fn grpname(mut self, grpname: impl Into<String>) -> Self {
+        self.grpname = grpname.into();
+        self
+    }
+
+    /// Unsolicited notification flags (`flags`).
+    pub fn flags(mut self, flags: TpInitFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Application specific data (`data`, `datalen`).
+    pub fn data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// Join the application (tpinit) with the collected settings.
+    /// See *tpinit(3)* for more details.
+    pub fn init(self) -> AtmiResult<()> {
+        let datalen = c_long::try_from(self.data.len())
+            .map_err(|_| AtmiError::new(raw::TPEINVAL, "TPINIT data too large"))?;
+
+        // TPINITNEED(): `data` is the start of the variable length data
+        let size = size_of::<raw::TPINIT>()
+            .max(offset_of!(raw::TPINIT, data) + self.data.len());
+
+        let buf = self.ctx.tpalloc("TPINIT", "", size)?;
+        let tpinfo = buf.as_ptr() as *mut raw::TPINIT;
+
+        let rc = unsafe {
+            let tp = &mut *tpinfo;
+            copy_ident(&mut tp.usrname, "usrname", &self.usrname)
+                .and_then(|_| copy_ident(&mut tp.cltname, "cltname", &self.cltname))
+                .and_then(|_| copy_ident(&mut tp.passwd, "passwd", &self.passwd))
+                .and_then(|_| copy_ident(&mut tp.grpname, "grpname", &self.grpname))
+                .and_then(|_| {
+                    tp.flags = self.flags.bits();
+                    tp.datalen = datalen;
+
+                    let data = std::ptr::addr_of_mut!(tp.data) as *mut u8;
+                    std::ptr::copy_nonoverlapping(self.data.as_ptr(), data, self.data.len());
+
+                    self.ctx.tpinit_with(tpinfo)
+                })
+        };
+
+        unsafe { (*tpinfo).passwd.zeroize() };
+
+        rc
+    }
+}
+
+/// Copy identification string to the fixed size `TPINIT` field, with EOS.
+fn copy_ident(field: &mut [c_char], name: &str, value: &str) -> AtmiResult<()> {
+    let bytes = value.as_bytes();
+
+    if bytes.len() >= field.len() || bytes.len() > raw::MAXTIDENT as usize {
+        return Err(AtmiError::new(
+            raw::TPEINVAL,
+            format!("TPINIT {name} longer than {} bytes", raw::MAXTIDENT),
+        ));
+    }
+
+    if bytes.contains(&0) {
+        return Err(AtmiError::new(
+            raw::TPEINVAL,
+            format!("TPINIT {name} contains NUL byte"),
+        ));
+    }
+
+    for (dst, src) in field.iter_mut().zip(bytes) {
+        *dst = *src as c_char;
+    }
+    field[bytes.len()] = 0;
+
+    Ok(())
+}
+
+impl AtmiCtx {
+    /// Start building `TPINIT` for joining the application with user, client
+    /// name, password, notification flags or data. See *tpinit(3)* for more details.
+    pub fn tpinit_builder(&self) -> TpInitBuilder<'_> {
+        TpInitBuilder::new(self)
+    }
+}
@@ -1,6 +1,7 @@
-use crate::{raw, AtmiCtx, TypedBuffer};
+use crate::{raw, AtmiCtx, AtmiError, AtmiResult, Conversation, DeferredRequest, TypedBuffer,
+    TypedBufferRef, TypedUbfRef};
 use core::ffi::c_char;
-use std::{ffi::CStr};
+use std::{ffi::CStr, time::Instant};
 
 /// Safe Rust wrapper for TPSVCINFO passed into a service callback.
 ///
@@ -10,6 +11,8 @@ use std::{ffi::CStr};
 pub struct TpSvcInfo<'ctx> {
     raw: *mut raw::TPSVCINFO,
     ctx: &'ctx AtmiCtx,
+    prio: i32,
+    deadline: Option<Instant>,
 }
 
 ///Service info returned by the service call
@@ -19,7 +22,10 @@ impl<'ctx> TpSvcInfo<'ctx> {
     /// - `ctx` must be the current ATMI context for this thread.
     /// - XATMI owns the memory; Rust must NOT free anything.
     pub unsafe fn from_raw(ctx: &'ctx AtmiCtx, raw: *mut raw::TPSVCINFO) -> Self {
-        TpSvcInfo { raw, ctx }
+        // priority of the request received, before the service calls others
+        let prio = ctx.with_ctx(|| unsafe { raw::tpgprio() });
+        let deadline = unsafe { crate::deadline::incoming_deadline(ctx, (*raw).data) };
+        TpSvcInfo { raw, ctx, prio, deadline }
     }
 
     #[inline]
@@ -50,6 +56,17 @@ impl<'ctx> TpSvcInfo<'ctx> {
         }
     }
 
+    /// Priority the request arrived with (see *tpgprio(3)*), -1 if unknown.
+    pub fn priority(&self) -> i32 {
+        self.prio
+    }
+
+    /// Deadline of the request propagated by the caller, see
+    /// `set_global_deadline_field`.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
     /// Input buffer length.
     pub fn len(&self) -> i64 {
         self.raw().len
@@ -71,6 +88,18 @@ impl<'ctx> TpSvcInfo<'ctx> {
         self.raw().cd
     }
 
+    /// Conversation with the caller, if service was invoked with *tpconnect(3)*.
+    ///
+    /// The service side does not disconnect; the conversation ends with the
+    /// service reply.
+    pub fn conversation(&self) -> Option<Conversation<'ctx>> {
+        if self.flags() & raw::TPCONV as i64 != 0 {
+            Some(Conversation::from_service(self.ctx, self.cd()))
+        } else {
+            None
+        }
+    }
+
     pub fn appkey(&self) -> i64 {
         self.raw().appkey
     }
@@ -79,16 +108,66 @@ impl<'ctx> TpSvcInfo<'ctx> {
         self.raw().cltid
     }
 
-    /// View the service buffer (`TPSVCINFO::data`) as a **TypedBufferView**.
+    /// View the service buffer (`TPSVCINFO::data`).
     ///
     /// This buffer is **not owned** by Rust; XATMI controls its lifetime.
-    ///
-    /// # Safety
-    /// You MUST ensure `ctx` matches the runtime's active context.
-    pub fn data(&self) -> TypedBuffer<'ctx> {
+    /// After `take_data()` the view is null.
+    pub fn data(&self) -> TypedBufferRef<'_> {
         let ptr = self.raw().data as *mut c_char;
-        let ret = unsafe { TypedBuffer::from_raw(self.ctx, ptr) };
-        ret
+        unsafe { TypedBufferRef::from_raw(self.ctx, ptr) }
+    }
+
+    /// View the service buffer (`TPSVCINFO::data`) as UBF.
+    /// Blind cast, caller knows the service receives UBF.
+    pub fn data_ubf(&self) -> TypedUbfRef<'_> {
+        TypedUbfRef::from_ref(self.data())
     }
-}
 
+    /// Take ownership of the service buffer, e.g. to modify it and return it
+    /// as the reply. Only the first call returns the buffer.
+    ///
+    /// Returned as the reply, the same buffer goes to *tpreturn(3)*, which
+    /// accepts the request buffer and does not free it again. Dropped or
+    /// reallocated, the buffer is freed through XATMI, which then does not
+    /// free the request buffer after the reply.
+    pub fn take_data(&mut self) -> Option<TypedBuffer<'ctx>> {
+        let ptr = self.raw().data;
+
+        if ptr.is_null() {
+            return None;
+        }
+
+        self.raw_mut().data = std::ptr::null_mut();
+        Some(unsafe { TypedBuffer::from_raw(self.ctx, ptr) })
+    }
+
+    /// Hand the request over to another thread (tpsrvgetctxdata).
+    ///
+    /// The handler shall then return `ServiceReply::Deferred`; the dispatcher
+    /// calls *tpcontinue(3)* and is free for the next request. The reply is
+    /// sent with `DeferredRequest::complete`.
+    /// See *tpsrvgetctxdata(3)* for more details.
+    ///
+    /// # Errors
+    ///
+    /// * `TPEPROTO` – not called from the service handler, or called twice.
+    pub fn defer(&self) -> AtmiResult<DeferredRequest> {
+        let Some(request) = crate::server::begin_defer() else {
+            return Err(AtmiError::new(
+                raw::TPEPROTO,
+                "defer() is allowed once, from the service handler",
+            ));
+        };
+
+        self.ctx.with_ctx(|| {
+            let ctxdata = unsafe { raw::tpsrvgetctxdata() };
+
+            if ctxdata.is_null() {
+                crate::server::cancel_defer();
+                Err(self.ctx.atmi_last_error())
+            } else {
+                Ok(DeferredRequest::new(ctxdata, self.name().to_string(), request))
+            }
+        })
+    }
+}
@@ -3,7 +3,9 @@ use core::ffi::{c_char, c_long};
 use crate::{raw, AtmiCtx, AtmiResult};
 
 use std::{
+    ffi::CStr,
     mem::ManuallyDrop,
+    ops::Deref,
 };
 
 #[derive(Debug)]
@@ -31,6 +33,45 @@ impl<'ctx> TypedBuffer<'ctx> {
         self.ptr
     }
 
+    /// Mutable access to the C pointer, for XATMI calls that may
+    /// reallocate the buffer or change its type in place (tpcall, tpgetrply...).
+    #[inline]
+    pub(crate) fn ptr_mut(&mut self) -> &mut *mut c_char {
+        &mut self.ptr
+    }
+
+    /// Query buffer type, sub-type and allocated size. See *tptypes(3)* for more details.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((type, subtype, size))` – e.g. `("UBF", "", 1024)`.
+    /// * `Err(e)` – if the underlying `tptypes` call fails.
+    pub fn tptypes(&self) -> AtmiResult<(String, String, usize)> {
+        // Buffers are larger than XATMI_TYPE_LEN / XATMI_SUBTYPE_LEN + EOS.
+        let mut type_ = [0 as c_char; 16];
+        let mut subtype = [0 as c_char; 32];
+
+        let size = self.ctx.with_ctx(|| {
+            let rc = unsafe {
+                raw::tptypes(self.ptr, type_.as_mut_ptr(), subtype.as_mut_ptr())
+            };
+
+            if rc == raw::EXFAIL as c_long {
+                Err(self.ctx.atmi_last_error())
+            } else {
+                Ok(rc as usize)
+            }
+        })?;
+
+        let (type_, subtype) = unsafe {
+            (
+                CStr::from_ptr(type_.as_ptr()).to_string_lossy().into_owned(),
+                CStr::from_ptr(subtype.as_ptr()).to_string_lossy().into_owned(),
+            )
+        };
+        Ok((type_, subtype, size))
+    }
+
     /// # Safety
     /// Retie this buffer to a *different* context.
     ///
@@ -50,19 +91,23 @@ impl<'ctx> TypedBuffer<'ctx> {
     /// On success, `self` will point to the new buffer.
     /// On failure, `self` remains valid and unchanged, and the error is returned.
     pub fn tprealloc(&mut self, new_size: usize) -> AtmiResult<()> {
-        let new_ptr = unsafe {
-            raw::tprealloc(self.ptr as *mut c_char, new_size as c_long)
-        };
+        let ctx = self.ctx;
 
-        if new_ptr.is_null() {
-            // C failed; original pointer still valid.
-            // Use the error from *this* context instance.
-            Err(self.ctx.atmi_last_error())
-        } else {
-            // Success, update pointer (may or may not have moved).
-            self.ptr = new_ptr;
-            Ok(())
-        }
+        ctx.with_ctx(|| {
+            let new_ptr = unsafe {
+                raw::tprealloc(self.ptr as *mut c_char, new_size as c_long)
+            };
+
+            if new_ptr.is_null() {
+                // C failed; original pointer still valid.
+                // Use the error from *this* context instance.
+                Err(ctx.atmi_last_error())
+            } else {
+                // Success, update pointer (may or may not have moved).
+                self.ptr = new_ptr;
+                Ok(())
+            }
+        })
     }
 
 }
@@ -70,7 +115,32 @@ impl<'ctx> TypedBuffer<'ctx> {
 impl<'ctx> Drop for TypedBuffer<'ctx> {
     fn drop(&mut self) {
         if !self.ptr.is_null() {
-            unsafe { raw::tpfree(self.ptr) }
+            self.ctx.with_ctx(|| unsafe { raw::tpfree(self.ptr) })
         }
     }
 }
+
+/// Borrowed, non-owning view of a buffer owned by XATMI (e.g. `TPSVCINFO::data`).
+///
+/// Dereferences to `TypedBuffer` for read access, nothing is freed on drop.
+#[derive(Debug)]
+pub struct TypedBufferRef<'a> {
+    inner: ManuallyDrop<TypedBuffer<'a>>,
+}
+
+impl<'a> TypedBufferRef<'a> {
+    /// # Safety
+    /// `raw` must be a valid `atmibuf*` (or null) which outlives `'a`.
+    pub unsafe fn from_raw(ctx: &'a AtmiCtx, raw: *mut c_char) -> Self {
+        TypedBufferRef { inner: ManuallyDrop::new(TypedBuffer::from_raw(ctx, raw)) }
+    }
+}
+
+impl<'a> Deref for TypedBufferRef<'a> {
+    type Target = TypedBuffer<'a>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
@@ -1,8 +1,11 @@
 // src/typed_ubf.rs
 use core::ffi::{c_char, c_long, c_int};
-use std::ops::{Deref, DerefMut};
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
 
-use crate::{raw, AtmiCtx, AtmiError, TypedBuffer, UbfResult, UbfError};
+use crate::{raw, AtmiCtx, AtmiError, TypedBuffer, TypedBufferRef, UbfResult, UbfError};
 
 ///UBF field value
 pub enum UbfValue<'ctx> {
@@ -73,14 +76,16 @@ impl<'ctx> TypedUbf<'ctx> {
     /// * `Err(e)` – if the underlying `Bsizeof` call fails.
     pub fn bsizeof(&mut self) -> UbfResult<usize> {
 
-        let rc = unsafe {raw::Bsizeof(self.inner.as_ptr() as *mut raw::UBFH)};
+        self.ctx.with_ctx(|| {
+            let rc = unsafe {raw::Bsizeof(self.inner.as_ptr() as *mut raw::UBFH)};
 
-        if raw::EXFAIL as c_long ==rc {
-            //Generate error.
-            Err(self.ctx.ubf_last_error())
-        } else {
-            Ok(rc as usize)
-        }
+            if raw::EXFAIL as c_long ==rc {
+                //Generate error.
+                Err(self.ctx.ubf_last_error())
+            } else {
+                Ok(rc as usize)
+            }
+        })
     }
 
     /// Reallocate the buffer twice of the size
@@ -171,23 +176,28 @@ impl<'ctx> TypedUbf<'ctx> {
                 // TODO: Add support for view
             };
 
-            // One CBchg() call
-            let rc = unsafe {
-                raw::CBchg(
-                    self.as_ubfh(),
-                    bfldid as raw::BFLDID,
-                    occ as raw::BFLDOCC,
-                    ptr,
-                    len,
-                    ftype as c_int,
-                )
-            };
+            // One CBchg() call, error is read in the same context
+            let ctx = self.inner.ctx;
+            let rc = ctx.with_ctx(|| {
+                let rc = unsafe {
+                    raw::CBchg(
+                        self.as_ubfh(),
+                        bfldid as raw::BFLDID,
+                        occ as raw::BFLDOCC,
+                        ptr,
+                        len,
+                        ftype as c_int,
+                    )
+                };
 
-            if rc == 0 {
-                return Ok(());
-            } else {
-                let err = self.inner.ctx.ubf_last_error();
+                if rc == 0 {
+                    Ok(())
+                } else {
+                    Err(ctx.ubf_last_error())
+                }
+            });
 
+            if let Err(err) = rc {
                 if err.code == UbfError::BNOSPACE && realloc {
                     // Reallocate the buffer to twice the size and retry.
                     self.grow_buffer()?;
@@ -196,6 +206,7 @@ impl<'ctx> TypedUbf<'ctx> {
                     return Err(err);
                 }
             }
+            return Ok(());
         }
     } // bchg()
 
@@ -216,3 +227,33 @@ impl<'ctx> DerefMut for TypedUbf<'ctx> {
         &mut self.inner
     }
 }
+
+/// Borrowed, non-owning view of a UBF buffer owned by XATMI.
+///
+/// Dereferences to `TypedUbf` for read access, nothing is freed on drop.
+#[derive(Debug)]
+pub struct TypedUbfRef<'a> {
+    inner: ManuallyDrop<TypedUbf<'a>>,
+}
+
+impl<'a> TypedUbfRef<'a> {
+    /// # Safety
+    /// `raw` must be a valid UBF (`UBFH*`) which outlives `'a`.
+    pub unsafe fn from_raw(ctx: &'a AtmiCtx, raw: *mut c_char) -> Self {
+        TypedUbfRef { inner: ManuallyDrop::new(TypedUbf::from_raw(ctx, raw)) }
+    }
+
+    /// Blind cast from a borrowed buffer you know is UBF.
+    pub fn from_ref(buf: TypedBufferRef<'a>) -> Self {
+        unsafe { TypedUbfRef::from_raw(buf.ctx, buf.as_ptr()) }
+    }
+}
+
+impl<'a> Deref for TypedUbfRef<'a> {
+    type Target = TypedUbf<'a>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
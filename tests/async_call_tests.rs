@@ -0,0 +1,82 @@
+#![cfg(feature = "tokio")]
+
+use endurox_rs::AsyncCaller;
+use endurox_rs::AtmiError;
+use endurox_rs::CallFlags;
+
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn async_call_no_service() {
+    let caller = AsyncCaller::new().expect("failed to start async caller");
+
+    let err = caller
+        .call(
+            "NO_SUCH_SVC",
+            CallFlags::TPNOTRAN,
+            |ctx| ctx.tpalloc("UBF", "", 1024),
+            |reply| reply.map(drop).map_err(AtmiError::from),
+        )
+        .await
+        .expect_err("call to missing service shall fail");
+
+    assert_eq!(err.code, AtmiError::TPENOENT);
+}
+
+#[tokio::test]
+#[ignore = "needs examples/test_server booted"]
+async fn async_call_service_failure() {
+    let caller = AsyncCaller::new().expect("failed to start async caller");
+
+    // TPFAIL with rcode 5, seen by parse
+    let (code, urcode) = caller
+        .call(
+            "RSFAIL",
+            CallFlags::TPNOTRAN,
+            |ctx| ctx.tpalloc("UBF", "", 1024),
+            |reply| match reply {
+                Ok(_) => Ok((0, 0)),
+                Err(e) => Ok((e.code, e.urcode)),
+            },
+        )
+        .await
+        .expect("parse shall get the failure");
+
+    assert_eq!(code, AtmiError::TPESVCFAIL);
+    assert_eq!(urcode, 5);
+}
+
+#[tokio::test]
+#[ignore = "needs examples/test_server booted"]
+async fn async_call_not_delayed_by_slow_call() {
+    let caller = AsyncCaller::new().expect("failed to start async caller");
+
+    let slow = caller.call(
+        "RSSLOW",
+        CallFlags::TPNOTRAN,
+        |ctx| ctx.tpalloc("UBF", "", 1024),
+        |reply| reply.map(drop).map_err(AtmiError::from),
+    );
+
+    let fast = async {
+        // let the slow call be sent first
+        tokio::task::yield_now().await;
+        let started = Instant::now();
+
+        caller
+            .call(
+                "RSECHO",
+                CallFlags::TPNOTRAN,
+                |ctx| ctx.tpalloc("UBF", "", 1024),
+                |reply| reply.map(drop).map_err(AtmiError::from),
+            )
+            .await
+            .map(|()| started.elapsed())
+    };
+
+    let (slow, fast) = tokio::join!(slow, fast);
+    slow.expect("slow call failed");
+    let elapsed = fast.expect("fast call failed");
+
+    assert!(elapsed < Duration::from_secs(1), "fast call took {elapsed:?}");
+}
@@ -57,3 +57,83 @@ fn tpalloc_ubf() {
     drop(buf2);
     drop(ctx);
 }
+
+#[test]
+fn enter_nested_rejected() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    let other = AtmiCtx::new().expect("failed to create AtmiCtx");
+
+    let guard = ctx.enter().expect("enter failed");
+
+    let err = other.enter().expect_err("nested enter shall fail");
+    assert_eq!(err.code, endurox_rs::AtmiError::TPEPROTO);
+
+    drop(guard);
+    other.enter().expect("enter after guard drop shall be OK");
+}
+
+#[test]
+fn tpinit_builder_cltname_too_long() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+
+    let err = ctx
+        .tpinit_builder()
+        .cltname("X".repeat(64))
+        .passwd("secret")
+        .init()
+        .expect_err("too long cltname shall fail");
+    assert_eq!(err.code, endurox_rs::AtmiError::TPEINVAL);
+}
+
+#[cfg(not(feature = "ctx-send"))]
+#[test]
+fn joined_shared_by_thread_contexts() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    let other = AtmiCtx::new().expect("failed to create AtmiCtx2");
+
+    // Both contexts use the C context of the thread
+    ctx.tpinit().expect("tpinit failed");
+    assert!(other.is_joined());
+
+    other.tpterm().expect("tpterm failed");
+    assert!(!ctx.is_joined());
+}
+
+#[cfg(not(feature = "ctx-send"))]
+#[test]
+fn helper_ctx_drop_keeps_session() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    // Did not tpinit itself, thus drop shall not tpterm the thread's session
+    drop(AtmiCtx::new().expect("failed to create AtmiCtx2"));
+    assert!(ctx.is_joined());
+}
+
+#[cfg(feature = "ctx-send")]
+#[test]
+fn moved_ctx_keeps_own_errors() {
+    use endurox_rs::{AtmiError, Priority, UbfError};
+
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+    let mut buf = ctx.tpalloc_ubf(1024).expect("Shall Alloc buffer OK");
+    buf.bchg(0, 0, UbfValue::Long(5), false).expect_err("bad field id shall fail");
+    drop(buf);
+    ctx.tpalloc("NO_SUCH_TYPE", "", 0).expect_err("unknown buffer type shall fail");
+
+    // other context of this thread gets other errors meanwhile
+    let other = AtmiCtx::new().expect("failed to create AtmiCtx2");
+    other.tpinit().expect("tpinit failed");
+    other.tpsprio(Priority::Absolute(500)).expect_err("priority above 100 shall fail");
+
+    let (tperrno, ferror) = std::thread::spawn(move || {
+        (ctx.atmi_last_error().code, ctx.ubf_last_error().code)
+    })
+    .join()
+    .expect("thread failed");
+
+    assert_eq!(tperrno, AtmiError::TPENOENT);
+    assert_eq!(ferror, UbfError::BBADFLD);
+    assert_eq!(other.atmi_last_error().code, AtmiError::TPEINVAL);
+}
@@ -0,0 +1,99 @@
+use endurox_rs::AtmiCtx;
+use endurox_rs::AtmiError;
+use endurox_rs::CallFlags;
+use endurox_rs::ConvEvent;
+use endurox_rs::TypedBuffer;
+
+use std::ffi::CStr;
+
+/// STRING buffer with `text`.
+fn string_buf<'a>(ctx: &'a AtmiCtx, text: &str) -> TypedBuffer<'a> {
+    let buf = ctx.tpalloc("STRING", "", text.len() + 1).expect("tpalloc failed");
+    unsafe {
+        std::ptr::copy_nonoverlapping(text.as_ptr(), buf.as_ptr() as *mut u8, text.len());
+        *buf.as_ptr().add(text.len()) = 0;
+    }
+    buf
+}
+
+fn buf_text(buf: &TypedBuffer<'_>) -> String {
+    unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+#[test]
+fn tpconnect_not_joined() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+
+    let err = ctx
+        .tpconnect("NO_SUCH_SVC", None, CallFlags::TPSENDONLY)
+        .expect_err("tpconnect without tpinit shall fail");
+    assert_eq!(err.code, AtmiError::TPEPROTO);
+}
+
+#[test]
+fn tpconnect_no_service() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "ping");
+    let err = ctx
+        .tpconnect("NO_SUCH_SVC", Some(&mut buf), CallFlags::TPRECVONLY)
+        .expect_err("tpconnect to missing service shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+}
+
+#[test]
+fn conv_event_is_final() {
+    assert!(!ConvEvent::SendOnly.is_final());
+    for ev in [ConvEvent::SvcSucc, ConvEvent::SvcFail, ConvEvent::DisconImm, ConvEvent::SvcErr] {
+        assert!(ev.is_final());
+    }
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn conversation_service_success() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "ping");
+    let mut conv = ctx
+        .tpconnect("RSCONV", Some(&mut buf), CallFlags::TPRECVONLY)
+        .expect("tpconnect failed");
+
+    let (data, ev) = conv.recv(CallFlags::empty()).expect("recv failed");
+    assert_eq!(ev, None);
+    assert_eq!(buf_text(&data), "pong");
+
+    let (_, ev) = conv.recv(CallFlags::empty()).expect("recv failed");
+    assert_eq!(ev, Some(ConvEvent::SvcSucc));
+    assert!(!conv.is_open());
+    assert_eq!(ctx.tpurcode(), 3);
+
+    // finished conversation is not disconnected (tpdiscon would fail)
+    conv.discon().expect("discon of finished conversation shall be no-op");
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn conversation_service_failure() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "fail");
+    let mut conv = ctx
+        .tpconnect("RSCONV", Some(&mut buf), CallFlags::TPRECVONLY)
+        .expect("tpconnect failed");
+
+    let (data, ev) = conv.recv(CallFlags::empty()).expect("recv failed");
+    assert_eq!(ev, None);
+    assert_eq!(buf_text(&data), "pong");
+
+    let (_, ev) = conv.recv(CallFlags::empty()).expect("recv failed");
+    assert_eq!(ev, Some(ConvEvent::SvcFail));
+    assert!(!conv.is_open());
+    assert_eq!(ctx.tpurcode(), 4);
+
+    // dropped without tpdiscon
+    drop(conv);
+}
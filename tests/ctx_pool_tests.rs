@@ -0,0 +1,29 @@
+#![cfg(feature = "ctx-send")]
+
+use endurox_rs::AtmiCtxPool;
+use endurox_rs::AtmiError;
+
+use std::time::Duration;
+
+#[test]
+fn pool_lease_and_timeout() {
+    let pool = AtmiCtxPool::new(2).expect("failed to create pool");
+
+    std::thread::scope(|s| {
+        let l1 = pool.acquire().expect("acquire 1 failed");
+        let l2 = s
+            .spawn(|| pool.acquire().expect("acquire 2 failed").is_joined())
+            .join()
+            .unwrap();
+        assert!(l1.is_joined() && l2);
+    });
+
+    let _l1 = pool.acquire().expect("acquire 1 failed");
+    let _l2 = pool.acquire().expect("acquire 2 failed");
+    assert_eq!(pool.metrics().in_use, 2);
+
+    let err = pool
+        .acquire_timeout(Duration::from_millis(10))
+        .expect_err("exhausted pool shall time out");
+    assert_eq!(err.code, AtmiError::TPETIME);
+}
@@ -0,0 +1,25 @@
+use endurox_rs::AtmiError;
+use endurox_rs::CallFlags;
+use endurox_rs::CtxDispatcher;
+
+#[test]
+fn dispatcher_runs_jobs_on_worker_contexts() {
+    let dispatcher = CtxDispatcher::new(2).expect("failed to start dispatcher");
+
+    let joined = dispatcher.submit(|ctx| ctx.is_joined());
+
+    let call = dispatcher.submit(|ctx| {
+        let mut buf = ctx.tpalloc_ubf(1024)?;
+        ctx.tpcall_ubf("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+            .map(|_| ())
+            .map_err(AtmiError::from)
+    });
+
+    assert!(joined.wait().expect("job failed"));
+
+    let err = call
+        .wait()
+        .expect("job failed")
+        .expect_err("call to missing service shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+}
@@ -0,0 +1,253 @@
+#![cfg(feature = "server")]
+
+
+use endurox_rs::AtmiCtx;
+use endurox_rs::AtmiError;
+use endurox_rs::CallFlags;
+use endurox_rs::ServiceReply;
+use endurox_rs::TypedBuffer;
+
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+/// BFLD_LONG field 1001 carrying the deadline, same as in the test server
+const DEADLINE_FLD: i32 = (1 << 25) | 1001;
+
+/// STRING buffer with `text`.
+fn string_buf<'a>(ctx: &'a AtmiCtx, text: &str) -> TypedBuffer<'a> {
+    let buf = ctx.tpalloc("STRING", "", text.len() + 1).expect("tpalloc failed");
+    unsafe {
+        std::ptr::copy_nonoverlapping(text.as_ptr(), buf.as_ptr() as *mut u8, text.len());
+        *buf.as_ptr().add(text.len()) = 0;
+    }
+    buf
+}
+
+fn buf_text(buf: &TypedBuffer<'_>) -> String {
+    unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+#[test]
+fn advertise_name_too_long() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+
+    // 31 chars, XATMI_SERVICE_NAME_LENGTH is 30; rejected before tpadvertise
+    let name = "S".repeat(31);
+
+    let err = ctx
+        .advertise(&name, |_ctx, _info| Ok(ServiceReply::success(None)))
+        .expect_err("too long name shall fail");
+    assert_eq!(err.code, AtmiError::TPEINVAL);
+
+    let err = ctx.unadvertise(&name).expect_err("too long name shall fail");
+    assert_eq!(err.code, AtmiError::TPEINVAL);
+}
+
+#[test]
+fn periodic_callback_zero_period() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+
+    let err = ctx
+        .set_periodic_callback(std::time::Duration::ZERO, |_ctx| Ok(()))
+        .expect_err("zero period shall fail");
+    assert_eq!(err.code, AtmiError::TPEINVAL);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn svcinfo_take_data_round_trip() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "hello");
+
+    let reply = ctx.tpcall("RSECHO", &mut buf, CallFlags::TPNOTRAN).expect("RSECHO failed");
+    assert_eq!(buf_text(&reply), "hello");
+
+    // request buffer is not consumed
+    assert_eq!(buf_text(&buf), "hello");
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn svcinfo_take_data_once() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "hello");
+
+    // taken buffer is dropped in the service, freed once
+    ctx.tpcall("RSTAKE", &mut buf, CallFlags::TPNOTRAN).expect("RSTAKE failed");
+    assert_eq!(ctx.tpurcode(), 1);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn tpcall_fail_reply_only_with_data() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "hello");
+
+    // no data sent with TPFAIL, the pre-allocated reply buffer is not given
+    let err = ctx.tpcall("RSFAIL", &mut buf, CallFlags::TPNOTRAN).expect_err("RSFAIL shall fail");
+    assert!(err.is_service_failure());
+    assert_eq!(err.urcode, 5);
+    assert!(err.reply.is_none());
+
+    let err = ctx
+        .tpcall("RSFAILECHO", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("RSFAILECHO shall fail");
+    assert!(err.is_service_failure());
+    assert_eq!(err.reply.as_ref().map(buf_text).as_deref(), Some("hello"));
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn svcinfo_data_view() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "hello");
+
+    ctx.tpcall("RSLEN", &mut buf, CallFlags::TPNOTRAN).expect("RSLEN failed");
+    assert_eq!(ctx.tpurcode(), 5);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn server_thread_hooks_ran() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("STRING", "", 16).expect("tpalloc failed");
+
+    // test_server runs with two dispatch threads (_tmstartserver hooks)
+    ctx.tpcall("RSTHREADS", &mut buf, CallFlags::TPNOTRAN).expect("RSTHREADS failed");
+    assert_eq!(ctx.tpurcode(), 2);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn deferred_complete() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("STRING", "", 16).expect("tpalloc failed");
+
+    ctx.tpcall("RSDEFER", &mut buf, CallFlags::TPNOTRAN).expect("RSDEFER failed");
+    assert_eq!(ctx.tpurcode(), 7);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn deferred_drop_without_complete() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("STRING", "", 16).expect("tpalloc failed");
+
+    let err = ctx
+        .tpcall("RSDEFDROP", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("dropped request shall fail");
+    assert_eq!(err.code, AtmiError::TPESVCFAIL);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn deferred_drop_within_handler() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("STRING", "", 16).expect("tpalloc failed");
+
+    let err = ctx
+        .tpcall("RSDEFERR", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("failed handler shall fail the request");
+    assert_eq!(err.code, AtmiError::TPESVCFAIL);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn poller_fd_registered_in_init() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("STRING", "", 16).expect("tpalloc failed");
+
+    // pipe registered in init gets its callback from the server main loop
+    let polled = (0..50).any(|_| {
+        ctx.tpcall("RSPOLLED", &mut buf, CallFlags::TPNOTRAN).expect("RSPOLLED failed");
+        let polled = ctx.tpurcode() == 1;
+        if !polled {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        polled
+    });
+    assert!(polled, "poller callback not invoked");
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn fan_out_other_call_outstanding() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = string_buf(&ctx, "hello");
+    let mut call = ctx
+        .tpacall("RSECHO", &mut buf, CallFlags::TPNOTRAN)
+        .expect("tpacall failed")
+        .expect("reply expected");
+
+    // reply of RSECHO would be taken by the fan-out, nothing is sent
+    let results = ctx
+        .fan_out()
+        .call(1, "RSECHO", string_buf(&ctx, "fan"))
+        .run();
+    let err = results[&1].as_ref().expect_err("fan-out shall fail");
+    assert_eq!(err.code, AtmiError::TPEPROTO);
+
+    let reply = call.reply(CallFlags::empty()).expect("RSECHO failed");
+    assert_eq!(buf_text(&reply), "hello");
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn deadline_propagated_for_one_call() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+    endurox_rs::set_global_deadline_field(Some(DEADLINE_FLD));
+
+    let mut buf = string_buf(&ctx, "hello");
+
+    ctx.set_deadline(Some(Instant::now() + Duration::from_secs(30)));
+    ctx.tpcall("RSDEADLINE", &mut buf, CallFlags::TPNOTRAN).expect("RSDEADLINE failed");
+    assert_eq!(ctx.tpurcode(), 1);
+
+    // call info is removed after the call, the buffer goes without deadline
+    ctx.set_deadline(None);
+    ctx.tpcall("RSDEADLINE", &mut buf, CallFlags::TPNOTRAN).expect("RSDEADLINE failed");
+    assert_eq!(ctx.tpurcode(), 0);
+
+    endurox_rs::set_global_deadline_field(None);
+}
+
+#[test]
+#[ignore = "needs examples/test_server booted"]
+fn fan_out_capped_by_deadline() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    // RSSLOW replies in 2 seconds, the deadline passes first
+    let started = Instant::now();
+    ctx.set_deadline(Some(started + Duration::from_millis(500)));
+
+    let results = ctx
+        .fan_out()
+        .call(1, "RSSLOW", string_buf(&ctx, "slow"))
+        .timeout(Duration::from_secs(10))
+        .run();
+    let err = results[&1].as_ref().expect_err("fan-out shall time out");
+    assert_eq!(err.code, AtmiError::TPETIME);
+    assert!(started.elapsed() < Duration::from_secs(2), "fan-out took {:?}", started.elapsed());
+}
@@ -0,0 +1,280 @@
+
+use endurox_rs::AtmiCtx;
+use endurox_rs::AtmiError;
+use endurox_rs::BlkTimeScope;
+use endurox_rs::CallFlags;
+use endurox_rs::CallOptions;
+use endurox_rs::CircuitBreaker;
+use endurox_rs::Priority;
+use endurox_rs::RetryPolicy;
+use endurox_rs::UbfValue;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn tpcall_no_service() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc_ubf(1024).expect("Shall Alloc buffer OK");
+    buf.bchg(1, 0, UbfValue::Long(5), false).expect("Bchg failed");
+
+    let err = ctx
+        .tpcall_ubf("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call to missing service shall fail");
+
+    assert_eq!(err.code, AtmiError::TPENOENT);
+
+    // request buffer is still ours
+    assert!(!buf.as_ptr().is_null());
+}
+
+#[test]
+fn tpacall_no_service() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc_ubf(1024).expect("Shall Alloc buffer OK");
+
+    let err = ctx
+        .tpacall("NO_SUCH_SVC", &mut buf, CallFlags::TPNOREPLY)
+        .expect_err("call to missing service shall fail");
+
+    assert_eq!(err.code, AtmiError::TPENOENT);
+}
+
+#[test]
+fn tpcall_not_joined() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    assert!(!ctx.is_joined());
+
+    let mut buf = ctx.tpalloc_ubf(1024).expect("Shall Alloc buffer OK");
+
+    let err = ctx
+        .tpcall_ubf("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call without tpinit shall fail");
+
+    assert_eq!(err.code, AtmiError::TPEPROTO);
+}
+
+#[test]
+fn blktime_scope_restores_previous() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let prev = ctx.tpgblktime(BlkTimeScope::All).expect("tpgblktime failed");
+
+    {
+        let _guard = ctx
+            .blktime_scope(Duration::from_millis(2500), BlkTimeScope::All)
+            .expect("blktime_scope failed");
+
+        // rounded up to whole seconds
+        let cur = ctx.tpgblktime(BlkTimeScope::All).expect("tpgblktime failed");
+        assert_eq!(cur, Duration::from_secs(3));
+    }
+
+    let cur = ctx.tpgblktime(BlkTimeScope::All).expect("tpgblktime failed");
+    assert_eq!(cur, prev);
+}
+
+#[test]
+fn tpsprio_out_of_range() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    ctx.tpsprio(Priority::Absolute(75)).expect("tpsprio failed");
+
+    let err = ctx
+        .tpsprio(Priority::Absolute(500))
+        .expect_err("priority above 100 shall fail");
+    assert_eq!(err.code, AtmiError::TPEINVAL);
+}
+
+#[test]
+fn tpcall_error_carries_service() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc_ubf(1024).expect("Shall Alloc buffer OK");
+
+    let err = ctx
+        .tpcall_ubf("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call to missing service shall fail");
+
+    assert_eq!(err.service, "NO_SUCH_SVC");
+    assert!(!err.is_service_failure());
+    assert!(err.reply.is_none());
+}
+
+#[test]
+fn fan_out_no_services() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let results = ctx
+        .fan_out()
+        .call(1, "NO_SUCH_SVC1", ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed"))
+        .call(2, "NO_SUCH_SVC2", ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed"))
+        .flags(CallFlags::TPNOTRAN)
+        .timeout(Duration::from_secs(5))
+        .run();
+
+    assert_eq!(results.len(), 2);
+    for key in [1, 2] {
+        let err = results[&key].as_ref().expect_err("call to missing service shall fail");
+        assert_eq!(err.code, AtmiError::TPENOENT);
+    }
+}
+
+#[test]
+fn fan_out_duplicate_key() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    // reported by run() for all requests, nothing is sent
+    let results = ctx
+        .fan_out()
+        .call(1, "NO_SUCH_SVC1", ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed"))
+        .call(2, "NO_SUCH_SVC2", ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed"))
+        .call(1, "NO_SUCH_SVC3", ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed"))
+        .run();
+
+    assert_eq!(results.len(), 2);
+    for key in [1, 2] {
+        let err = results[&key].as_ref().expect_err("duplicate key shall fail the fan-out");
+        assert_eq!(err.code, AtmiError::TPEINVAL);
+    }
+}
+
+#[test]
+fn tpcall_retry_opens_circuit() {
+    let mut ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+    ctx.set_circuit_breaker(Some(breaker.clone()));
+
+    let mut buf = ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed");
+    let opts = CallOptions::new()
+        .flags(CallFlags::TPNOTRAN)
+        .retry(RetryPolicy::new().max_attempts(3).backoff(Duration::ZERO, Duration::ZERO));
+
+    // 3 attempts count as one failure
+    let err = ctx
+        .tpcall_opts("NO_SUCH_SVC", &mut buf, &opts)
+        .expect_err("call to missing service shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+    assert!(!breaker.is_open("NO_SUCH_SVC"));
+
+    let err = ctx
+        .tpcall_opts("NO_SUCH_SVC", &mut buf, &opts)
+        .expect_err("call to missing service shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+    assert!(breaker.is_open("NO_SUCH_SVC"));
+
+    let err = ctx
+        .tpcall_opts("NO_SUCH_SVC", &mut buf, &opts)
+        .expect_err("call with open circuit shall fail");
+    assert_eq!(err.code, AtmiError::TPELIMIT);
+    assert_eq!(err.service, "NO_SUCH_SVC");
+}
+
+#[test]
+fn circuit_half_open_trial_reopens() {
+    let mut ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_millis(200)));
+    ctx.set_circuit_breaker(Some(breaker.clone()));
+
+    let mut buf = ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed");
+    let opts = CallOptions::new().flags(CallFlags::TPNOTRAN);
+
+    ctx.tpcall_opts("NO_SUCH_SVC", &mut buf, &opts).expect_err("call shall fail");
+    assert!(breaker.is_open("NO_SUCH_SVC"));
+
+    // after open_for the trial call goes through, its failure opens again
+    std::thread::sleep(Duration::from_millis(250));
+    assert!(!breaker.is_open("NO_SUCH_SVC"));
+
+    let err = ctx.tpcall_opts("NO_SUCH_SVC", &mut buf, &opts).expect_err("trial shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+    assert!(breaker.is_open("NO_SUCH_SVC"));
+}
+
+#[test]
+fn tpcall_deadline_passed() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed");
+    ctx.set_deadline(Some(Instant::now()));
+
+    let err = ctx
+        .tpcall("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call after deadline shall fail");
+    assert_eq!(err.code, AtmiError::TPETIME);
+
+    ctx.set_deadline(None);
+    let err = ctx
+        .tpcall("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call to missing service shall fail");
+    assert_eq!(err.code, AtmiError::TPENOENT);
+}
+
+#[test]
+fn tpcall_other_ctx_entered() {
+    let entered = AtmiCtx::new().expect("failed to create AtmiCtx");
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed");
+    let _guard = entered.enter().expect("enter failed");
+
+    // buffer of other context than the entered one is rejected in debug builds
+    let err = ctx
+        .tpcall("NO_SUCH_SVC", &mut buf, CallFlags::TPNOTRAN)
+        .expect_err("call shall fail");
+    if cfg!(debug_assertions) {
+        assert_eq!(err.code, AtmiError::TPEINVAL);
+    } else {
+        assert_eq!(err.code, AtmiError::TPENOENT);
+    }
+}
+
+#[test]
+fn tpcall_opts_rejected_keeps_next_timeout() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    let mut buf = ctx.tpalloc("UBF", "", 1024).expect("tpalloc failed");
+    let opts = CallOptions::new().timeout(Duration::from_secs(7));
+
+    // rejected before the call, the timeout is not left for the next call
+    let err = ctx
+        .tpcall_opts("NO\0SVC", &mut buf, &opts)
+        .expect_err("service name with NUL shall fail");
+    assert_eq!(err.code, AtmiError::TPEINVAL);
+    assert_eq!(ctx.tpgblktime(BlkTimeScope::Next).expect("tpgblktime failed"), Duration::ZERO);
+
+    ctx.set_deadline(Some(Instant::now()));
+    let err = ctx
+        .tpcall_opts("NO_SUCH_SVC", &mut buf, &opts)
+        .expect_err("call after deadline shall fail");
+    assert_eq!(err.code, AtmiError::TPETIME);
+    assert_eq!(ctx.tpgblktime(BlkTimeScope::Next).expect("tpgblktime failed"), Duration::ZERO);
+}
+
+#[test]
+fn getrply_deadline_passed() {
+    let ctx = AtmiCtx::new().expect("failed to create AtmiCtx");
+    ctx.tpinit().expect("tpinit failed");
+
+    ctx.set_deadline(Some(Instant::now()));
+    let err = ctx
+        .getrply_any(CallFlags::empty())
+        .expect_err("reply wait after deadline shall fail");
+    assert_eq!(err.code, AtmiError::TPETIME);
+}